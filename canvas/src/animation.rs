@@ -0,0 +1,631 @@
+//! Animated GIF and APNG output for recordings, written a frame at a time so that no intermediate
+//! PNGs are left on disk.
+//!
+//! Pass `--record out.gif` (or `out.png`/`out.apng`) to a sketch to record its frames, with `--fps`
+//! for the frame rate and `--loop` for how many times it plays, 0 meaning forever. GIFs are limited
+//! to 256 colours, so their palette is built from the sketch's own colours and the blends between
+//! them that anti-aliasing and translucency produce.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use image::RgbaImage;
+
+use crate::args::Args;
+use crate::encode::{self, Compression};
+use crate::metadata::{self, Metadata, SIGNATURE};
+
+pub const DEFAULT_FPS: u16 = 30;
+/// The most in-between shades added for each pair of palette colours.
+const MAX_BLEND_STEPS: usize = 15;
+const GIF_COLORS: usize = 256;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    /// The format to write to `path`, from its extension: `.gif`, or `.png` and `.apng` for APNG.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "gif" => Some(AnimationFormat::Gif),
+            "png" | "apng" => Some(AnimationFormat::Apng),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationSettings {
+    pub fps: u16,
+    /// How many times the animation plays, 0 for forever.
+    pub plays: u16,
+    /// The colours a GIF is quantized to. Left empty, a general purpose palette is used.
+    pub palette: Vec<[u8; 3]>,
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        AnimationSettings {
+            fps: DEFAULT_FPS,
+            plays: 0,
+            palette: Vec::new(),
+        }
+    }
+}
+
+impl AnimationSettings {
+    /// Read `--fps` and `--loop`.
+    pub fn from_args(args: &Args) -> Self {
+        AnimationSettings {
+            fps: args.parsed("fps").unwrap_or(DEFAULT_FPS).max(1),
+            plays: args.parsed("loop").unwrap_or(0),
+            palette: Vec::new(),
+        }
+    }
+
+    pub fn with_palette(mut self, palette: Vec<[u8; 3]>) -> Self {
+        self.palette = palette;
+        self
+    }
+}
+
+/// A GIF palette from a sketch's weighted colours and its background: the colours themselves, then
+/// evenly spaced blends between every pair of them. Colours weighted zero are never drawn and are
+/// left out.
+pub fn palette(colors: &[((u8, u8, u8), f32)], background: (u8, u8, u8)) -> Vec<[u8; 3]> {
+    let mut base = vec![[background.0, background.1, background.2]];
+    for &((r, g, b), weight) in colors {
+        if weight > 0.0 && !base.contains(&[r, g, b]) {
+            base.push([r, g, b]);
+        }
+    }
+    let pairs = base.len() * (base.len() - 1) / 2;
+    let steps = (GIF_COLORS.saturating_sub(base.len()) / pairs.max(1)).min(MAX_BLEND_STEPS);
+    let mut palette = base.clone();
+    for (i, a) in base.iter().enumerate() {
+        for b in &base[i + 1..] {
+            for step in 1..=steps {
+                let t = step as f32 / (steps + 1) as f32;
+                palette.push([0, 1, 2].map(|c| mix(a[c], b[c], t)));
+            }
+        }
+    }
+    palette.truncate(GIF_COLORS);
+    palette
+}
+
+fn mix(a: u8, b: u8, t: f32) -> u8 {
+    (a as f32 + (b as f32 - a as f32) * t).round() as u8
+}
+
+/// The 216 web-safe colours and 40 greys, for sketches without a fixed palette.
+fn general_palette() -> Vec<[u8; 3]> {
+    let levels = [0, 51, 102, 153, 204, 255];
+    let mut palette = Vec::with_capacity(GIF_COLORS);
+    for r in levels {
+        for g in levels {
+            for b in levels {
+                palette.push([r, g, b]);
+            }
+        }
+    }
+    for i in 0..40u16 {
+        let grey = (i * 255 / 39) as u8;
+        if !levels.contains(&grey) {
+            palette.push([grey; 3]);
+        }
+    }
+    palette
+}
+
+/// An animation being written to disk, one frame at a time.
+pub struct Animation {
+    width: u32,
+    height: u32,
+    frames: u32,
+    encoder: Encoder,
+}
+
+enum Encoder {
+    Gif {
+        encoder: gif::Encoder<File>,
+        file: File,
+        quantizer: Quantizer,
+        delay: u16,
+    },
+    Apng {
+        out: BufWriter<File>,
+        /// Where the animation control chunk starts, to fill in the frame count at the end.
+        control: u64,
+        sequence: u32,
+        fps: u16,
+        plays: u32,
+    },
+}
+
+impl Animation {
+    /// Start writing an animation of `width` x `height` frames to `path`, creating its directory if
+    /// needed.
+    pub fn create(
+        path: &Path,
+        width: u32,
+        height: u32,
+        format: AnimationFormat,
+        settings: &AnimationSettings,
+    ) -> io::Result<Self> {
+        Animation::new(create(path)?, width, height, format, settings)
+    }
+
+    fn new(
+        file: File,
+        width: u32,
+        height: u32,
+        format: AnimationFormat,
+        settings: &AnimationSettings,
+    ) -> io::Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(invalid("an animation needs at least one pixel"));
+        }
+        let encoder = match format {
+            AnimationFormat::Gif => gif_encoder(file, width, height, settings)?,
+            AnimationFormat::Apng => apng_encoder(file, width, height, settings)?,
+        };
+        Ok(Animation {
+            width,
+            height,
+            frames: 0,
+            encoder,
+        })
+    }
+
+    pub fn add_frame(&mut self, image: &RgbaImage) -> io::Result<()> {
+        if image.dimensions() != (self.width, self.height) {
+            return Err(invalid(&format!(
+                "frame is {}x{} but the animation is {}x{}",
+                image.width(),
+                image.height(),
+                self.width,
+                self.height
+            )));
+        }
+        match &mut self.encoder {
+            Encoder::Gif {
+                encoder,
+                quantizer,
+                delay,
+                ..
+            } => {
+                let frame = gif::Frame {
+                    width: self.width as u16,
+                    height: self.height as u16,
+                    delay: *delay,
+                    buffer: quantizer.indices(image).into(),
+                    ..gif::Frame::default()
+                };
+                encoder.write_frame(&frame).map_err(gif_error)?;
+            }
+            Encoder::Apng {
+                out, sequence, fps, ..
+            } => {
+                let data = image_data(image)?;
+                let mut chunks = Vec::new();
+                let mut control = Vec::with_capacity(26);
+                control.extend_from_slice(&sequence.to_be_bytes());
+                control.extend_from_slice(&self.width.to_be_bytes());
+                control.extend_from_slice(&self.height.to_be_bytes());
+                // At the top left, shown for 1/fps seconds, neither disposed of nor blended.
+                control.extend_from_slice(&[0; 8]);
+                control.extend_from_slice(&1u16.to_be_bytes());
+                control.extend_from_slice(&fps.to_be_bytes());
+                control.extend_from_slice(&[0, 0]);
+                metadata::write_chunk(&mut chunks, b"fcTL", &control);
+                *sequence += 1;
+                if self.frames == 0 {
+                    // The first frame doubles as the still image for viewers without APNG support.
+                    metadata::write_chunk(&mut chunks, b"IDAT", &data);
+                } else {
+                    let mut frame_data = sequence.to_be_bytes().to_vec();
+                    frame_data.extend_from_slice(&data);
+                    metadata::write_chunk(&mut chunks, b"fdAT", &frame_data);
+                    *sequence += 1;
+                }
+                out.write_all(&chunks)?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Finish the file and return how many frames it holds.
+    pub fn finish(self) -> io::Result<u32> {
+        if self.frames == 0 {
+            return Err(invalid("no frames were recorded"));
+        }
+        match self.encoder {
+            Encoder::Gif { encoder, file, .. } => {
+                // The encoder writes the trailer when dropped.
+                drop(encoder);
+                file.sync_all()?;
+            }
+            Encoder::Apng {
+                mut out,
+                control,
+                plays,
+                ..
+            } => {
+                let mut end = Vec::new();
+                metadata::write_chunk(&mut end, b"IEND", &[]);
+                out.write_all(&end)?;
+                let mut file = out.into_inner().map_err(|err| err.into_error())?;
+                file.seek(SeekFrom::Start(control))?;
+                file.write_all(&animation_control(self.frames, plays))?;
+                file.sync_all()?;
+            }
+        }
+        Ok(self.frames)
+    }
+}
+
+fn gif_encoder(
+    file: File,
+    width: u32,
+    height: u32,
+    settings: &AnimationSettings,
+) -> io::Result<Encoder> {
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(invalid("GIFs are at most 65535 pixels on a side"));
+    }
+    let mut palette = if settings.palette.is_empty() {
+        general_palette()
+    } else {
+        settings.palette.clone()
+    };
+    palette.truncate(GIF_COLORS);
+    let colors: Vec<u8> = palette.iter().flatten().copied().collect();
+    // Frames are written unbuffered to a second handle, so that the file can be synced once the
+    // encoder has written its trailer and been dropped.
+    let mut encoder = gif::Encoder::new(file.try_clone()?, width as u16, height as u16, &colors)
+        .map_err(gif_error)?;
+    let repeat = match settings.plays {
+        0 => gif::Repeat::Infinite,
+        // The first play is not a repeat.
+        plays => gif::Repeat::Finite(plays - 1),
+    };
+    encoder.set_repeat(repeat).map_err(gif_error)?;
+    Ok(Encoder::Gif {
+        encoder,
+        file,
+        quantizer: Quantizer::new(palette),
+        // In hundredths of a second.
+        delay: (100.0 / settings.fps.max(1) as f32).round().max(1.0) as u16,
+    })
+}
+
+fn apng_encoder(
+    file: File,
+    width: u32,
+    height: u32,
+    settings: &AnimationSettings,
+) -> io::Result<Encoder> {
+    let mut out = BufWriter::new(file);
+    let mut header = SIGNATURE.to_vec();
+    let mut image_header = Vec::with_capacity(13);
+    image_header.extend_from_slice(&width.to_be_bytes());
+    image_header.extend_from_slice(&height.to_be_bytes());
+    // 8 bit RGBA, deflate, adaptive filtering, no interlacing.
+    image_header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    metadata::write_chunk(&mut header, b"IHDR", &image_header);
+    let control = header.len() as u64;
+    // The frame count is filled in once it is known.
+    header.extend_from_slice(&animation_control(0, settings.plays as u32));
+    out.write_all(&header)?;
+    Ok(Encoder::Apng {
+        out,
+        control,
+        sequence: 0,
+        fps: settings.fps.max(1),
+        plays: settings.plays as u32,
+    })
+}
+
+/// The `acTL` chunk, frame count and number of plays.
+fn animation_control(frames: u32, plays: u32) -> Vec<u8> {
+    let mut data = frames.to_be_bytes().to_vec();
+    data.extend_from_slice(&plays.to_be_bytes());
+    let mut chunk = Vec::with_capacity(20);
+    metadata::write_chunk(&mut chunk, b"acTL", &data);
+    chunk
+}
+
+/// The compressed image data of `image`, as it would go in the `IDAT` chunks of a PNG.
+fn image_data(image: &RgbaImage) -> io::Result<Vec<u8>> {
+    let png = encode::png_bytes(image, Compression::Default)?;
+    let mut data = Vec::new();
+    for chunk in metadata::chunks(&png)? {
+        if &chunk.kind == b"IDAT" {
+            data.extend_from_slice(&png[chunk.data]);
+        }
+    }
+    Ok(data)
+}
+
+/// Maps colours onto the nearest palette entry, remembering every colour it has seen since frames
+/// of a recording share most of their colours.
+struct Quantizer {
+    palette: Vec<[u8; 3]>,
+    nearest: HashMap<[u8; 3], u8>,
+}
+
+impl Quantizer {
+    fn new(palette: Vec<[u8; 3]>) -> Self {
+        Quantizer {
+            palette,
+            nearest: HashMap::new(),
+        }
+    }
+
+    fn indices(&mut self, image: &RgbaImage) -> Vec<u8> {
+        image
+            .pixels()
+            .map(|pixel| self.index([pixel[0], pixel[1], pixel[2]]))
+            .collect()
+    }
+
+    fn index(&mut self, color: [u8; 3]) -> u8 {
+        let palette = &self.palette;
+        *self.nearest.entry(color).or_insert_with(|| {
+            let distance = |entry: &[u8; 3]| -> u32 {
+                (0..3)
+                    .map(|c| (entry[c] as i32 - color[c] as i32).pow(2) as u32)
+                    .sum()
+            };
+            (0..palette.len())
+                .min_by_key(|&i| distance(&palette[i]))
+                .unwrap_or(0) as u8
+        })
+    }
+}
+
+/// Frames for an animation, taken from any thread and in any order and written in order of their
+/// frame number. The animation takes the size of the first frame. Cloning gives another handle to
+/// the same recording.
+#[derive(Clone)]
+pub struct Recorder {
+    path: PathBuf,
+    format: AnimationFormat,
+    state: Arc<Mutex<RecorderState>>,
+}
+
+struct RecorderState {
+    /// The file, until the first frame arrives and the animation can be started.
+    file: Option<File>,
+    settings: AnimationSettings,
+    animation: Option<Animation>,
+    /// Frames that arrived before the ones preceding them, keyed by frame number.
+    pending: BTreeMap<u64, RgbaImage>,
+    next: u64,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, settings: &AnimationSettings) -> io::Result<Self> {
+        let format = AnimationFormat::from_path(path)
+            .ok_or_else(|| invalid("recordings are written as .gif, or .png or .apng for APNG"))?;
+        let file = create(path)?;
+        Ok(Recorder {
+            path: path.to_path_buf(),
+            format,
+            state: Arc::new(Mutex::new(RecorderState {
+                file: Some(file),
+                settings: settings.clone(),
+                animation: None,
+                pending: BTreeMap::new(),
+                next: 0,
+            })),
+        })
+    }
+
+    /// The animation asked for with `--record <path>`, if any, quantized to `palette` when written
+    /// as a GIF. Exits if it cannot be created.
+    pub fn from_args(args: &Args, palette: Vec<[u8; 3]>) -> Option<Self> {
+        let path = PathBuf::from(args.value("record")?);
+        let settings = AnimationSettings::from_args(args).with_palette(palette);
+        match Recorder::create(&path, &settings) {
+            Ok(recorder) => Some(recorder),
+            Err(err) => {
+                eprintln!("Cannot record to {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Add frame `nth`, counting from 0. Frames are held back until those before them arrive.
+    pub fn add_frame(&self, nth: u64, image: RgbaImage) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if nth < state.next {
+            return Ok(());
+        }
+        state.pending.insert(nth, image);
+        state.flush(self.format, false)
+    }
+
+    /// Write whatever frames are still held back, in order, and finish the file. APNGs are stamped
+    /// with `metadata` like any other PNG; GIFs have nowhere to keep it.
+    pub fn finish(&self, metadata: &Metadata) -> io::Result<u32> {
+        let mut state = self.state.lock().unwrap();
+        state.flush(self.format, true)?;
+        let animation = match (state.animation.take(), state.file.take()) {
+            (Some(animation), _) => animation,
+            (None, Some(_)) => return Err(invalid("no frames were recorded")),
+            (None, None) => return Err(invalid("the recording was already finished")),
+        };
+        let frames = animation.finish()?;
+        if self.format == AnimationFormat::Apng {
+            metadata.stamp(&self.path)?;
+        }
+        Ok(frames)
+    }
+}
+
+impl RecorderState {
+    /// Write the pending frames that follow on from the last one written, or all of them if `all`.
+    fn flush(&mut self, format: AnimationFormat, all: bool) -> io::Result<()> {
+        while let Some(entry) = self.pending.first_entry() {
+            if *entry.key() != self.next && !all {
+                break;
+            }
+            let nth = *entry.key();
+            let image = entry.remove();
+            if let Some(file) = self.file.take() {
+                let (width, height) = image.dimensions();
+                self.animation = Some(Animation::new(file, width, height, format, &self.settings)?);
+            }
+            if let Some(animation) = &mut self.animation {
+                animation.add_frame(&image)?;
+            }
+            self.next = nth + 1;
+        }
+        Ok(())
+    }
+}
+
+/// Create the file at `path` and the directory it goes in.
+fn create(path: &Path) -> io::Result<File> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    File::create(path)
+}
+
+fn gif_error(err: gif::EncodingError) -> io::Error {
+    match err {
+        gif::EncodingError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidInput, err),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::animation::{palette, Animation, AnimationFormat, AnimationSettings, Recorder};
+    use crate::metadata::{self, Metadata};
+    use crate::testing::TempDir;
+    use image::{Rgba, RgbaImage};
+
+    fn frame(shade: u8) -> RgbaImage {
+        RgbaImage::from_pixel(4, 3, Rgba([shade, 0, 255 - shade, 255]))
+    }
+
+    #[test]
+    fn palettes_hold_the_sketch_colours_and_their_blends() {
+        let colors = [((255, 0, 0), 0.5), ((0, 0, 255), 0.5), ((0, 255, 0), 0.0)];
+        let palette = palette(&colors, (255, 255, 255));
+        assert_eq!(&palette[..3], &[[255, 255, 255], [255, 0, 0], [0, 0, 255]]);
+        // Halfway between red and blue, with 15 steps between each of the three pairs.
+        assert!(palette.contains(&[128, 0, 128]));
+        assert_eq!(palette.len(), 3 + 3 * 15);
+        assert!(!palette.contains(&[0, 255, 0]));
+    }
+
+    #[test]
+    fn gifs_hold_every_frame_and_loop() {
+        let dir = TempDir::new("animation");
+        let path = dir.join("frames.gif");
+        let settings = AnimationSettings {
+            fps: 25,
+            plays: 0,
+            palette: palette(&[((255, 0, 0), 1.0), ((0, 0, 255), 1.0)], (0, 0, 0)),
+        };
+        let mut animation =
+            Animation::create(&path, 4, 3, AnimationFormat::Gif, &settings).unwrap();
+        for shade in [0, 255, 0] {
+            animation.add_frame(&frame(shade)).unwrap();
+        }
+        assert_eq!(animation.finish().unwrap(), 3);
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options
+            .read_info(std::fs::File::open(&path).unwrap())
+            .unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.delay, frame.buffer[..4].to_vec()));
+        }
+        assert_eq!(
+            frames,
+            vec![
+                (4, vec![0, 0, 255, 255]),
+                (4, vec![255, 0, 0, 255]),
+                (4, vec![0, 0, 255, 255]),
+            ]
+        );
+        // The NETSCAPE2.0 looping extension, set to repeat forever.
+        let bytes = std::fs::read(&path).unwrap();
+        let at = bytes.windows(11).position(|w| w == b"NETSCAPE2.0").unwrap();
+        assert_eq!(&bytes[at + 11..at + 15], &[3, 1, 0, 0]);
+    }
+
+    #[test]
+    fn apngs_are_valid_and_count_their_frames() {
+        let dir = TempDir::new("animation");
+        let path = dir.join("frames.png");
+        let settings = AnimationSettings {
+            fps: 12,
+            plays: 2,
+            ..AnimationSettings::default()
+        };
+        let recorder = Recorder::create(&path, &settings).unwrap();
+        // Out of order, as captured frames arrive.
+        recorder.add_frame(1, frame(255)).unwrap();
+        recorder.add_frame(0, frame(0)).unwrap();
+        recorder.add_frame(2, frame(100)).unwrap();
+        let stamp = Metadata::default().with(metadata::SKETCH, "test");
+        assert_eq!(recorder.finish(&stamp).unwrap(), 3);
+
+        let png = std::fs::read(&path).unwrap();
+        let chunks = metadata::chunks(&png).unwrap();
+        let kinds: Vec<&[u8]> = chunks.iter().map(|chunk| &chunk.kind[..]).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &b"IHDR"[..],
+                b"tEXt",
+                b"acTL",
+                b"fcTL",
+                b"IDAT",
+                b"fcTL",
+                b"fdAT",
+                b"fcTL",
+                b"fdAT",
+                b"IEND"
+            ]
+        );
+        for chunk in &chunks {
+            let crc_at = chunk.data.end;
+            let mut crc = crc32fast::Hasher::new();
+            crc.update(&chunk.kind);
+            crc.update(&png[chunk.data.clone()]);
+            assert_eq!(crc.finalize().to_be_bytes(), png[crc_at..crc_at + 4]);
+        }
+        // Three frames, played twice.
+        assert_eq!(&png[chunks[2].data.clone()], &[0, 0, 0, 3, 0, 0, 0, 2]);
+        assert_eq!(Metadata::read(&path).unwrap(), stamp);
+        // Viewers without APNG support show the first frame.
+        let still = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(still, frame(0));
+    }
+}
@@ -0,0 +1,135 @@
+//! Saves a sketch's generated model as JSON and loads it back, so a composition can be re-rendered
+//! later without running generation again.
+//!
+//! Pass `--save-model <path>` to write the model after generating it, or `--load-model <path>` to
+//! skip generation and use a model saved earlier.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::args::Args;
+
+pub fn save<T: Serialize>(model: &T, path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(writer, model)?;
+    Ok(())
+}
+
+pub fn load<T: DeserializeOwned>(path: &Path) -> io::Result<T> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// Load the model named by `--load-model`, or build a new one with `generate`. Either way the model
+/// is then written to `--save-model` if given.
+pub fn load_or_generate<T, F>(args: &Args, generate: F) -> T
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce() -> T,
+{
+    let model = match args.value("load-model") {
+        Some(path) => match load(Path::new(path)) {
+            Ok(model) => {
+                println!("Loaded {}", path);
+                model
+            }
+            Err(err) => {
+                eprintln!("Cannot load model from {}: {}", path, err);
+                std::process::exit(1);
+            }
+        },
+        None => generate(),
+    };
+    if let Some(path) = args.value("save-model") {
+        save(&model, Path::new(path)).expect("failed to save model");
+        println!("Saved {}", path);
+    }
+    model
+}
+
+/// Serde support for `Rect`, as its x and y ranges so that it round trips exactly. Use with
+/// `#[serde(with = "canvas::archive::rect")]`.
+pub mod rect {
+    use nannou::geom::{Range, Rect};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Ranges {
+        x: [f32; 2],
+        y: [f32; 2],
+    }
+
+    pub fn serialize<S: Serializer>(rect: &Rect, serializer: S) -> Result<S::Ok, S::Error> {
+        Ranges {
+            x: [rect.x.start, rect.x.end],
+            y: [rect.y.start, rect.y.end],
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rect, D::Error> {
+        let Ranges { x, y } = Ranges::deserialize(deserializer)?;
+        Ok(Rect {
+            x: Range::new(x[0], x[1]),
+            y: Range::new(y[0], y[1]),
+        })
+    }
+}
+
+/// Serde support for a list of `Tri`s, each as its three vertices. Use with
+/// `#[serde(with = "canvas::archive::tris")]`.
+pub mod tris {
+    use nannou::geom::Tri;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        tris: &[Tri<[f32; 2]>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(tris.iter().map(|tri| tri.0))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Tri<[f32; 2]>>, D::Error> {
+        let vertices = Vec::<[[f32; 2]; 3]>::deserialize(deserializer)?;
+        Ok(vertices.into_iter().map(Tri).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::TempDir;
+    use nannou::geom::{Rect, Tri, Vec2};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct Model {
+        #[serde(with = "crate::archive::rect")]
+        window: Rect,
+        #[serde(with = "crate::archive::tris")]
+        triangles: Vec<Tri<[f32; 2]>>,
+    }
+
+    #[test]
+    fn models_round_trip_through_json() {
+        let model = Model {
+            window: Rect::from_xy_wh(Vec2::new(1.0, -2.0), Vec2::new(640.0, 480.0)),
+            triangles: vec![Tri([[0.0, 0.0], [1.5, 0.0], [0.0, 2.25]])],
+        };
+        let dir = TempDir::new("archive");
+        let path = dir.join("model.json");
+        crate::archive::save(&model, &path).unwrap();
+        let loaded: Model = crate::archive::load(&path).unwrap();
+
+        assert_eq!(loaded.window, model.window);
+        assert_eq!(loaded.triangles[0].0, model.triangles[0].0);
+    }
+}
@@ -0,0 +1,99 @@
+//! Minimal command line handling shared by the sketches.
+//!
+//! Options look like `--name value`, `--name=value` or a bare `--flag`.
+
+use std::str::FromStr;
+
+pub struct Args {
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    pub fn from_env() -> Self {
+        Args::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Self {
+        let mut options: Vec<(String, Option<String>)> = Vec::new();
+        for arg in args {
+            if let Some(name) = arg.strip_prefix("--") {
+                match name.split_once('=') {
+                    Some((name, value)) => {
+                        options.push((name.to_string(), Some(value.to_string())))
+                    }
+                    None => options.push((name.to_string(), None)),
+                }
+            } else if let Some((_, value @ None)) = options.last_mut() {
+                *value = Some(arg);
+            }
+        }
+        Args { options }
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(n, _)| n == name)
+    }
+
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, v)| v.as_deref())
+    }
+
+    /// Parse the value of `name`, exiting with a readable message if it is malformed.
+    pub fn parsed<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.value(name).map(|value| match value.parse() {
+            Ok(parsed) => parsed,
+            Err(_) => {
+                eprintln!("invalid value for --{}: {}", name, value);
+                std::process::exit(2);
+            }
+        })
+    }
+
+    /// Parse a `WIDTHxHEIGHT` pixel size such as `--size 1920x1080`.
+    pub fn size(&self, name: &str) -> Option<(u32, u32)> {
+        self.value(name).map(|value| match parse_size(value) {
+            Some(size) => size,
+            None => {
+                eprintln!(
+                    "invalid value for --{}: {} (expected WIDTHxHEIGHT)",
+                    name, value
+                );
+                std::process::exit(2);
+            }
+        })
+    }
+}
+
+fn parse_size(value: &str) -> Option<(u32, u32)> {
+    let (w, h) = value.split_once('x')?;
+    Some((w.parse().ok()?, h.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::Args;
+
+    fn args(line: &str) -> Args {
+        Args::parse(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parses_flags_and_values() {
+        let args = args("--headless --out frames/art.png --size=640x480");
+        assert!(args.flag("headless"));
+        assert!(!args.flag("svg"));
+        assert_eq!(args.value("headless"), None);
+        assert_eq!(args.value("out"), Some("frames/art.png"));
+        assert_eq!(args.size("size"), Some((640, 480)));
+    }
+
+    #[test]
+    fn later_values_win() {
+        let args = args("--seed 1 --seed 2");
+        assert_eq!(args.parsed::<u64>("seed"), Some(2));
+    }
+}
@@ -0,0 +1,180 @@
+//! Re-renders a PNG from the metadata a sketch stamped into it.
+//!
+//! `replay frames/cells.png --out frames/again.png` prints what the image records and runs the same
+//! sketch headless with the recorded seed, size and options. A recorded frame is rendered again by
+//! stepping the sketch offline up to its frame number. The sketch has to be built into the same
+//! target directory first, e.g. with `cargo build --release`. Pass `--dry-run` to print the command
+//! without running it.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use canvas::args::Args;
+use canvas::metadata::{self, Metadata};
+
+fn main() {
+    let args = Args::from_env();
+    let png = match std::env::args().nth(1).filter(|a| !a.starts_with("--")) {
+        Some(png) => PathBuf::from(png),
+        None => {
+            eprintln!("usage: replay <png> [--out <path>] [--dry-run]");
+            std::process::exit(2);
+        }
+    };
+    let recorded = match Metadata::read(&png) {
+        Ok(recorded) => recorded,
+        Err(err) => {
+            eprintln!("Cannot read {}: {}", png.display(), err);
+            std::process::exit(1);
+        }
+    };
+    for (key, value) in recorded.entries() {
+        println!("{}: {}", key, value);
+    }
+    let sketch = match recorded.get(metadata::SKETCH) {
+        Some(sketch) => sketch,
+        None => {
+            eprintln!("{} does not record the sketch that made it", png.display());
+            std::process::exit(1);
+        }
+    };
+
+    let out = args
+        .value("out")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| replay_path(&png));
+    let mut arguments = recorded.parameters().unwrap_or_default();
+    // How and where the original was written, which the replay decides for itself.
+    for name in RUN_OPTIONS {
+        arguments = without(arguments, name);
+    }
+    if let Some(seed) = recorded.get(metadata::SEED) {
+        arguments = without(arguments, "seed");
+        arguments.extend(["--seed".to_string(), seed.to_string()]);
+    }
+    if let Some(size) = recorded.get(metadata::SIZE) {
+        arguments = without(arguments, "size");
+        arguments.extend(["--size".to_string(), size.to_string()]);
+    }
+    let frame = recorded
+        .get(metadata::FRAME)
+        .and_then(|frame| frame.parse::<u64>().ok());
+    // A frame is recorded on its own into a scratch directory and moved to `out` afterwards.
+    let scratch = std::env::temp_dir().join(format!("replay-{}", std::process::id()));
+    match frame {
+        Some(frame) => arguments.extend([
+            "--offline".to_string(),
+            (frame + 1).to_string(),
+            "--frames".to_string(),
+            frame.to_string(),
+            "--frames-dir".to_string(),
+            scratch.display().to_string(),
+            "--frame-name".to_string(),
+            FRAME_NAME.to_string(),
+        ]),
+        None => arguments.extend([
+            "--headless".to_string(),
+            "--out".to_string(),
+            out.display().to_string(),
+        ]),
+    }
+
+    let binary = sibling(sketch);
+    println!("Running {} {}", binary.display(), arguments.join(" "));
+    if args.flag("dry-run") {
+        return;
+    }
+    if !binary.exists() {
+        eprintln!(
+            "{} is not built, run `cargo build --release -p {}` first",
+            binary.display(),
+            sketch
+        );
+        std::process::exit(1);
+    }
+    let status = Command::new(&binary)
+        .args(&arguments)
+        .status()
+        .expect("failed to run the sketch");
+    if let (Some(frame), true) = (frame, status.success()) {
+        let recorded = scratch.join(FRAME_NAME.replace("{frame}", &frame.to_string()));
+        if let Err(err) = move_file(&recorded, &out) {
+            eprintln!(
+                "Cannot move {} to {}: {}",
+                recorded.display(),
+                out.display(),
+                err
+            );
+            std::process::exit(1);
+        }
+        let _ = fs::remove_dir_all(&scratch);
+        println!("Saved {}", out.display());
+    }
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+/// Options that say how a run is rendered and written rather than what it draws. The files they
+/// name belong to the original run, so a replay leaves them alone.
+const RUN_OPTIONS: [&str; 15] = [
+    "out",
+    "headless",
+    "offline",
+    "frames",
+    "every",
+    "record",
+    "resume",
+    "frames-dir",
+    "frame-name",
+    "svg",
+    "svg-layers",
+    "pdf",
+    "gcode",
+    "hpgl",
+    "save-model",
+];
+
+/// The name a replayed frame is recorded under in the scratch directory.
+const FRAME_NAME: &str = "{frame}.png";
+
+/// Move `from` to `to`, copying when they are on different file systems.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(dir) = to.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+/// `art.png` becomes `art-replay.png` next to it.
+fn replay_path(png: &Path) -> PathBuf {
+    let stem = png.file_stem().unwrap_or_default().to_string_lossy();
+    png.with_file_name(format!("{}-replay.png", stem))
+}
+
+/// The sketch's executable, next to this one in the target directory.
+fn sibling(sketch: &str) -> PathBuf {
+    let exe = std::env::current_exe().expect("failed to locate the replay executable");
+    exe.with_file_name(format!("{}{}", sketch, std::env::consts::EXE_SUFFIX))
+}
+
+/// Drop every `--name value` and `--name=value` from a command line.
+fn without(arguments: Vec<String>, name: &str) -> Vec<String> {
+    let flag = format!("--{}", name);
+    let mut kept = Vec::new();
+    let mut arguments = arguments.into_iter().peekable();
+    while let Some(argument) = arguments.next() {
+        if argument == flag {
+            if arguments.peek().is_some_and(|next| !next.starts_with("--")) {
+                arguments.next();
+            }
+        } else if !argument.starts_with(&format!("{}=", flag)) {
+            kept.push(argument);
+        }
+    }
+    kept
+}
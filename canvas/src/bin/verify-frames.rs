@@ -0,0 +1,65 @@
+//! Reports the gaps, damaged files and half written frames in a directory of recorded frames.
+//!
+//! `verify-frames cells/frames --frames 0..1200` lists the frames of the range that are missing.
+//! `--frame-name` and `--every` match the options the frames were recorded with, and `--sketch` and
+//! `--seed` narrow a directory shared by several runs down to one. Exits with 1 if anything is
+//! wrong, so it can gate a video encode.
+
+use std::path::PathBuf;
+
+use canvas::args::Args;
+use canvas::record::{FrameRange, NameTemplate};
+use canvas::verify::{self, Runs};
+
+fn main() {
+    let args = Args::from_env();
+    let dir = match std::env::args().nth(1).filter(|a| !a.starts_with("--")) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            eprintln!(
+                "usage: verify-frames <dir> [--frames <range>] [--every <n>] [--frame-name <template>] \
+                 [--sketch <name>] [--seed <seed>]"
+            );
+            std::process::exit(2);
+        }
+    };
+    let template: NameTemplate = args.parsed("frame-name").unwrap_or_default();
+    let mut frames: FrameRange = args.parsed("frames").unwrap_or_else(FrameRange::all);
+    if let Some(every) = args.parsed::<u64>("every") {
+        frames.every = every.max(1);
+    }
+    let report = match verify::scan(&dir, &template, args.value("sketch"), args.parsed("seed")) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Cannot read {}: {}", dir.display(), err);
+            std::process::exit(1);
+        }
+    };
+
+    let valid = report
+        .valid
+        .iter()
+        .filter(|&&nth| frames.contains(nth))
+        .count();
+    println!("{}: {} whole frames of {}", dir.display(), valid, frames);
+    let missing = report.missing(&frames);
+    if !missing.is_empty() {
+        println!(
+            "Missing {}: {}",
+            missing.len(),
+            Runs {
+                frames: &missing,
+                every: frames.every,
+            }
+        );
+    }
+    for (path, reason) in &report.corrupt {
+        println!("Damaged: {} {}", path.display(), reason);
+    }
+    for path in &report.partial {
+        println!("Unfinished: {}", path.display());
+    }
+    if !missing.is_empty() || !report.corrupt.is_empty() || !report.partial.is_empty() {
+        std::process::exit(1);
+    }
+}
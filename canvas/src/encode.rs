@@ -0,0 +1,451 @@
+//! A bounded pool of threads that render and write recorded frames in the background.
+//!
+//! Each frame takes a slot from the pool before it is captured and gives it back once it is on
+//! disk, so a sketch that records faster than frames can be written waits for room instead of
+//! piling frames up in memory. Frames are written under a temporary name and renamed into place,
+//! then listed in a completion log that is synced to disk, so a killed run leaves only whole frames
+//! behind and a record of which they are.
+//!
+//! `--encode-threads` sets the number of threads (one per core by default), `--encode-queue` how
+//! many frames may wait for them and `--compression` is `fast`, `default` or `best`.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use image::RgbaImage;
+
+use crate::args::Args;
+use crate::metadata::Metadata;
+use crate::raster;
+use crate::scene::Scene;
+
+/// Frames allowed to wait for a thread, per thread, unless `--encode-queue` says otherwise.
+const QUEUE_PER_THREAD: usize = 2;
+/// How long `reserve_polling` waits for room between calls to its `poll`.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Compression {
+    Fast,
+    Default,
+    Best,
+}
+
+impl From<Compression> for png::Compression {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::Fast => png::Compression::Fast,
+            Compression::Default => png::Compression::Default,
+            Compression::Best => png::Compression::Best,
+        }
+    }
+}
+
+impl From<Compression> for deflate::Compression {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::Fast => deflate::Compression::Fast,
+            Compression::Default => deflate::Compression::Default,
+            Compression::Best => deflate::Compression::Best,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CompressionParseErr {
+    UnknownLevelErr(String),
+}
+
+impl fmt::Display for CompressionParseErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompressionParseErr::UnknownLevelErr(s) => write!(
+                f,
+                "unknown compression {:?}, expected fast, default or best",
+                s
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompressionParseErr {}
+
+impl FromStr for Compression {
+    type Err = CompressionParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fast" => Ok(Compression::Fast),
+            "default" => Ok(Compression::Default),
+            "best" => Ok(Compression::Best),
+            _ => Err(CompressionParseErr::UnknownLevelErr(s.to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncodeSettings {
+    pub threads: usize,
+    /// How many frames may be waiting for or being written by a thread at once.
+    pub queue: usize,
+    pub compression: Compression,
+}
+
+impl Default for EncodeSettings {
+    fn default() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        EncodeSettings {
+            threads,
+            queue: threads * QUEUE_PER_THREAD,
+            compression: Compression::Default,
+        }
+    }
+}
+
+impl EncodeSettings {
+    /// Read `--encode-threads`, `--encode-queue` and `--compression`.
+    pub fn from_args(args: &Args) -> Self {
+        let threads = args
+            .parsed("encode-threads")
+            .unwrap_or(EncodeSettings::default().threads)
+            .max(1);
+        EncodeSettings {
+            threads,
+            queue: args
+                .parsed("encode-queue")
+                .unwrap_or(threads * QUEUE_PER_THREAD)
+                .max(1),
+            compression: args.parsed("compression").unwrap_or(Compression::Default),
+        }
+    }
+}
+
+/// What a thread turns into a PNG: a captured image, or a scene it renders first.
+pub enum Frame {
+    Image(RgbaImage),
+    Scene {
+        scene: Scene,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl Frame {
+    /// The width and height of the image, in pixels.
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            Frame::Image(image) => image.dimensions(),
+            Frame::Scene { width, height, .. } => (*width, *height),
+        }
+    }
+
+    pub fn into_image(self) -> RgbaImage {
+        match self {
+            Frame::Image(image) => image,
+            Frame::Scene {
+                scene,
+                width,
+                height,
+            } => raster::render(&scene, width, height),
+        }
+    }
+}
+
+/// A place in the pool's queue, held from before a frame is captured until it has been written.
+pub struct Slot {
+    shared: Arc<Shared>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.shared.in_flight.lock().unwrap() -= 1;
+        self.shared.room.notify_one();
+    }
+}
+
+struct Job {
+    nth: u64,
+    path: PathBuf,
+    frame: Frame,
+    metadata: Metadata,
+    _slot: Slot,
+}
+
+struct Shared {
+    capacity: usize,
+    in_flight: Mutex<usize>,
+    room: Condvar,
+    compression: Compression,
+    log: Option<Mutex<File>>,
+    written: Mutex<u32>,
+    /// The first thing that went wrong, reported when the pool is finished.
+    error: Mutex<Option<io::Error>>,
+}
+
+pub struct EncodePool {
+    shared: Arc<Shared>,
+    sender: Mutex<Option<Sender<Job>>>,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl EncodePool {
+    /// Start the threads. Each frame written is appended to the completion log at `log`, if given.
+    pub fn new(settings: &EncodeSettings, log: Option<&Path>) -> io::Result<Self> {
+        let log = match log {
+            Some(path) => {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                Some(Mutex::new(file))
+            }
+            None => None,
+        };
+        let shared = Arc::new(Shared {
+            capacity: settings.queue.max(1),
+            in_flight: Mutex::new(0),
+            room: Condvar::new(),
+            compression: settings.compression,
+            log,
+            written: Mutex::new(0),
+            error: Mutex::new(None),
+        });
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..settings.threads.max(1))
+            .map(|_| {
+                let shared = shared.clone();
+                let receiver = receiver.clone();
+                thread::spawn(move || work(&shared, &receiver))
+            })
+            .collect();
+        Ok(EncodePool {
+            shared,
+            sender: Mutex::new(Some(sender)),
+            workers: Mutex::new(workers),
+        })
+    }
+
+    /// Take a place in the queue, waiting for one to come free if it is full.
+    pub fn reserve(&self) -> Slot {
+        let mut in_flight = self.shared.in_flight.lock().unwrap();
+        while *in_flight >= self.shared.capacity {
+            in_flight = self.shared.room.wait(in_flight).unwrap();
+        }
+        *in_flight += 1;
+        Slot {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Like `reserve`, but calling `poll` while it waits, for frames that only reach the pool once
+    /// the waiting thread has done something, such as polling the GPU that captured them.
+    pub fn reserve_polling(&self, mut poll: impl FnMut()) -> Slot {
+        let mut in_flight = self.shared.in_flight.lock().unwrap();
+        while *in_flight >= self.shared.capacity {
+            drop(in_flight);
+            poll();
+            in_flight = self.shared.in_flight.lock().unwrap();
+            if *in_flight < self.shared.capacity {
+                break;
+            }
+            in_flight = self
+                .shared
+                .room
+                .wait_timeout(in_flight, POLL_INTERVAL)
+                .unwrap()
+                .0;
+        }
+        *in_flight += 1;
+        Slot {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Write frame `nth` to `path` in the background, stamped with `metadata`, in the place `slot`
+    /// was reserved for it.
+    pub fn submit(&self, slot: Slot, nth: u64, path: PathBuf, frame: Frame, metadata: Metadata) {
+        let job = Job {
+            nth,
+            path,
+            frame,
+            metadata,
+            _slot: slot,
+        };
+        let sent = match &*self.sender.lock().unwrap() {
+            Some(sender) => sender.send(job).is_ok(),
+            None => false,
+        };
+        if !sent {
+            self.shared
+                .fail(io::Error::other("frame submitted after the pool finished"));
+        }
+    }
+
+    /// Wait for every submitted frame to be written and return how many were, or the first error.
+    pub fn finish(&self) -> io::Result<u32> {
+        self.sender.lock().unwrap().take();
+        for worker in self.workers.lock().unwrap().drain(..) {
+            if worker.join().is_err() {
+                self.shared
+                    .fail(io::Error::other("a frame writing thread panicked"));
+            }
+        }
+        match self.shared.error.lock().unwrap().take() {
+            Some(err) => Err(err),
+            None => Ok(*self.shared.written.lock().unwrap()),
+        }
+    }
+}
+
+impl Shared {
+    fn fail(&self, err: io::Error) {
+        eprintln!("Cannot write frame: {}", err);
+        self.error.lock().unwrap().get_or_insert(err);
+    }
+
+    fn write(&self, job: Job) -> io::Result<()> {
+        let png = png_bytes(&job.frame.into_image(), self.compression)?;
+        let png = job.metadata.stamped(&png)?;
+        if let Some(dir) = job.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Synced and then renamed into place, so a frame on disk is always a whole one, even after
+        // a power cut that would otherwise lose data the rename had already made visible.
+        let partial = job.path.with_extension("png.partial");
+        let mut file = File::create(&partial)?;
+        file.write_all(&png)?;
+        file.sync_all()?;
+        fs::rename(&partial, &job.path)?;
+        if let Some(log) = &self.log {
+            let mut log = log.lock().unwrap();
+            writeln!(log, "{}\t{}", job.nth, job.path.display())?;
+            log.sync_data()?;
+        }
+        *self.written.lock().unwrap() += 1;
+        Ok(())
+    }
+}
+
+fn work(shared: &Shared, receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        // The lock is only held while waiting for the next job, not while writing it.
+        let job = match receiver.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        if let Err(err) = shared.write(job) {
+            shared.fail(err);
+        }
+    }
+}
+
+/// `image` encoded as an 8 bit RGBA PNG.
+pub(crate) fn png_bytes(image: &RgbaImage, compression: Compression) -> io::Result<Vec<u8>> {
+    let mut png = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png, image.width(), image.height());
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(compression);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer
+            .write_image_data(image.as_raw())
+            .map_err(io::Error::other)?;
+    }
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encode::{Compression, EncodePool, EncodeSettings, Frame};
+    use crate::metadata::{Metadata, SEED};
+    use crate::testing::TempDir;
+    use image::{Rgba, RgbaImage};
+    use std::sync::mpsc;
+    use std::sync::Arc;
+
+    #[test]
+    fn frames_are_written_logged_and_bounded() {
+        let dir = TempDir::new("encode");
+        let settings = EncodeSettings {
+            threads: 2,
+            queue: 3,
+            compression: Compression::Fast,
+        };
+        let log = dir.join("frames.log");
+        let pool = Arc::new(EncodePool::new(&settings, Some(&log)).unwrap());
+        let metadata = Metadata::default().with(SEED, 9);
+
+        // Three slots can be held at once, and a fourth only comes free once one is given back.
+        let held: Vec<_> = (0..3).map(|_| pool.reserve()).collect();
+        let (reserving, reserved) = mpsc::channel();
+        let waiting = {
+            let pool = pool.clone();
+            std::thread::spawn(move || {
+                reserving.send("reserving").unwrap();
+                let slot = pool.reserve();
+                reserving.send("reserved").unwrap();
+                slot
+            })
+        };
+        assert_eq!(reserved.recv().unwrap(), "reserving");
+        assert!(reserved.try_recv().is_err());
+        for (nth, slot) in held.into_iter().enumerate() {
+            let image = RgbaImage::from_pixel(2, 2, Rgba([nth as u8, 0, 0, 255]));
+            let path = dir.join(format!("{:05}.png", nth));
+            pool.submit(
+                slot,
+                nth as u64,
+                path,
+                Frame::Image(image),
+                metadata.clone(),
+            );
+        }
+        // Only returned once the frames above give their slots back.
+        assert_eq!(reserved.recv().unwrap(), "reserved");
+        drop(waiting.join().unwrap());
+
+        assert_eq!(pool.finish().unwrap(), 3);
+        let mut logged: Vec<String> = std::fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        logged.sort();
+        assert_eq!(logged.len(), 3);
+        assert!(logged[2].starts_with("2\t") && logged[2].ends_with("00002.png"));
+        let path = dir.join("00002.png");
+        assert_eq!(image::open(&path).unwrap().to_rgba8().get_pixel(0, 0)[0], 2);
+        assert_eq!(Metadata::read(&path).unwrap(), metadata);
+        assert!(!dir.join("00002.png.partial").exists());
+    }
+
+    #[test]
+    fn polling_reservations_poll_until_room_comes_free() {
+        let settings = EncodeSettings {
+            threads: 1,
+            queue: 1,
+            compression: Compression::Fast,
+        };
+        let pool = EncodePool::new(&settings, None).unwrap();
+        let mut held = Some(pool.reserve());
+        let mut polls = 0;
+        // The only slot is given back by the third poll, as a GPU readback would be.
+        let _slot = pool.reserve_polling(|| {
+            polls += 1;
+            if polls == 3 {
+                held.take();
+            }
+        });
+        assert_eq!(polls, 3);
+        assert!(held.is_none());
+    }
+}
@@ -0,0 +1,139 @@
+//! Writes whichever vector outputs were asked for on the command line.
+
+use std::path::Path;
+
+use nannou::geom::Vec2;
+
+use crate::args::Args;
+use crate::gcode::{self, GcodeSettings};
+use crate::hpgl;
+use crate::metadata::{self, Metadata};
+use crate::occlusion;
+use crate::optimize::OptimizeOptions;
+use crate::paper::Paper;
+use crate::pdf::{self, PdfMetadata, PdfOptions};
+use crate::plot::{Plot, PlotArea, PlotError};
+use crate::plotter::Plotter;
+use crate::print::PrintLayout;
+use crate::scene::Scene;
+use crate::svg;
+use crate::units::Mm;
+
+/// Write the scene to every vector format requested in `args`, e.g. `--svg art.svg`. Formats that
+/// record where they came from, such as PDF, take it from the sketch's `metadata`.
+pub fn write_requested(scene: &Scene, metadata: &Metadata, args: &Args) {
+    if let Some(path) = args.value("svg") {
+        // `--svg-layers` groups the shapes into one Inkscape layer per pen colour.
+        let save = if args.flag("svg-layers") {
+            svg::save_layers
+        } else {
+            svg::save
+        };
+        // Lines under later fills are cut away so that plotting the SVG matches the raster.
+        save(&occlusion::occlude(scene), Path::new(path)).expect("failed to save svg");
+        println!("Saved {}", path);
+    }
+
+    if let Some(path) = args.value("pdf") {
+        let (w, h) = page_size(args);
+        pdf::save(scene, w, h, &pdf_options(args, metadata), Path::new(path))
+            .expect("failed to save pdf");
+        println!("Saved {}", path);
+    }
+
+    let gcode_path = args.value("gcode");
+    let hpgl_path = args.value("hpgl");
+    if gcode_path.is_some() || hpgl_path.is_some() {
+        let settings = gcode_settings(args);
+        let area = plot_area(args);
+        let plot = Plot::place(scene, &area).unwrap_or_else(|err| refuse(err));
+        // Checked at the plot's full extent, before `--plot-clip` can cut it down to the paper.
+        let plotter = args.parsed::<Plotter>("plotter");
+        if let Some(plotter) = plotter {
+            if let Err(err) = plotter.check(&plot, &settings) {
+                refuse(err);
+            }
+        }
+        let mut plot = plot
+            .confine(&area, args.flag("plot-clip"))
+            .unwrap_or_else(|err| refuse(err));
+        if args.flag("optimize") {
+            // Plots are still in plot coordinates here, where G-code and HPGL start from the
+            // bottom left corner unless the plotter homes elsewhere.
+            let options = OptimizeOptions {
+                two_opt: args.flag("two-opt"),
+                start: plotter.map_or(Vec2::ZERO, |plotter| plotter.home(plot.paper_h)),
+                ..OptimizeOptions::default()
+            };
+            let report = plot.optimize(&options);
+            println!("Optimized plot (mm): {}", report);
+        }
+        if let Some(plotter) = plotter {
+            plot = plotter.to_machine(&plot);
+        }
+        if let Some(path) = gcode_path {
+            gcode::save(&plot, &settings, Path::new(path)).expect("failed to save g-code");
+            println!("Saved {}", path);
+        }
+        if let Some(path) = hpgl_path {
+            hpgl::save(&plot, Path::new(path)).expect("failed to save hpgl");
+            println!("Saved {}", path);
+        }
+    }
+}
+
+fn refuse(err: PlotError) -> ! {
+    eprintln!("Cannot export plot: {}", err);
+    std::process::exit(1);
+}
+
+/// The `--paper` (see `Paper::from_args`) with a `--margin-mm 15` border, defaulting to 10 mm.
+fn plot_area(args: &Args) -> PlotArea {
+    PlotArea::new(
+        Paper::from_args(args),
+        Mm(args.parsed("margin-mm").unwrap_or(10.0)),
+    )
+}
+
+/// The media box of the `--print` layout if there is one, otherwise the `--paper`.
+fn page_size(args: &Args) -> (Mm, Mm) {
+    match PrintLayout::from_args(args) {
+        Some(layout) => {
+            let media = layout.media_box();
+            (Mm(media.w() as f64), Mm(media.h() as f64))
+        }
+        None => {
+            let paper = Paper::from_args(args);
+            (paper.width(), paper.height())
+        }
+    }
+}
+
+/// `--cmyk` and `--spot-colors '#45868f=PANTONE 5473 C'`, along with the sketch, seed and command
+/// line recorded in `metadata`.
+fn pdf_options(args: &Args, metadata: &Metadata) -> PdfOptions {
+    PdfOptions {
+        cmyk: args.flag("cmyk"),
+        spot_colors: args.parsed("spot-colors").unwrap_or_default(),
+        metadata: PdfMetadata {
+            sketch: metadata
+                .get(metadata::SKETCH)
+                .unwrap_or_default()
+                .to_string(),
+            seed: metadata
+                .get(metadata::SEED)
+                .and_then(|seed| seed.parse().ok()),
+            parameters: metadata.parameters().unwrap_or_default().join(" "),
+        },
+    }
+}
+
+fn gcode_settings(args: &Args) -> GcodeSettings {
+    let defaults = GcodeSettings::default();
+    GcodeSettings {
+        pen_up_z: args.parsed("pen-up-z").unwrap_or(defaults.pen_up_z),
+        pen_down_z: args.parsed("pen-down-z").unwrap_or(defaults.pen_down_z),
+        draw_feed: args.parsed("feed").unwrap_or(defaults.draw_feed),
+        pen_feed: args.parsed("pen-feed").unwrap_or(defaults.pen_feed),
+    }
+}
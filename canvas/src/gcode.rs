@@ -0,0 +1,108 @@
+//! G-code for GRBL-style pen plotters that lift the pen on the Z axis.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::plot::Plot;
+
+pub struct GcodeSettings {
+    /// Z height with the pen lifted, in millimetres.
+    pub pen_up_z: f32,
+    /// Z height with the pen on the paper, in millimetres.
+    pub pen_down_z: f32,
+    /// Feed rate while drawing, in millimetres per minute.
+    pub draw_feed: f32,
+    /// Feed rate for lowering the pen, in millimetres per minute.
+    pub pen_feed: f32,
+}
+
+impl Default for GcodeSettings {
+    fn default() -> Self {
+        GcodeSettings {
+            pen_up_z: 5.0,
+            pen_down_z: 0.0,
+            draw_feed: 1500.0,
+            pen_feed: 500.0,
+        }
+    }
+}
+
+pub fn render(plot: &Plot, settings: &GcodeSettings) -> String {
+    let mut gcode = String::new();
+    writeln!(
+        gcode,
+        "; {} paths on {} x {} mm paper",
+        plot.paths.len(),
+        plot.paper_w,
+        plot.paper_h
+    )
+    .unwrap();
+    gcode.push_str("G21 ; millimetres\n");
+    gcode.push_str("G90 ; absolute positioning\n");
+    let pen_up = format!("G0 Z{:.3} ; pen up\n", settings.pen_up_z);
+    gcode.push_str(&pen_up);
+    for path in &plot.paths {
+        let start = path[0];
+        writeln!(gcode, "G0 X{:.3} Y{:.3}", start.x, start.y).unwrap();
+        writeln!(
+            gcode,
+            "G1 Z{:.3} F{} ; pen down",
+            settings.pen_down_z, settings.pen_feed
+        )
+        .unwrap();
+        for (i, p) in path[1..].iter().enumerate() {
+            if i == 0 {
+                writeln!(gcode, "G1 X{:.3} Y{:.3} F{}", p.x, p.y, settings.draw_feed).unwrap();
+            } else {
+                writeln!(gcode, "G1 X{:.3} Y{:.3}", p.x, p.y).unwrap();
+            }
+        }
+        gcode.push_str(&pen_up);
+    }
+    gcode.push_str("G0 X0 Y0\n");
+    gcode.push_str("M2\n");
+    gcode
+}
+
+pub fn save(plot: &Plot, settings: &GcodeSettings, path: &Path) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, render(plot, settings))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gcode::{render, GcodeSettings};
+    use crate::plot::Plot;
+    use nannou::geom::Vec2;
+
+    #[test]
+    fn pen_is_lifted_between_paths() {
+        let plot = Plot {
+            paper_w: 210.0,
+            paper_h: 297.0,
+            paths: vec![
+                vec![Vec2::new(10.0, 10.0), Vec2::new(20.0, 10.0)],
+                vec![
+                    Vec2::new(30.0, 30.0),
+                    Vec2::new(30.0, 40.0),
+                    Vec2::new(40.0, 40.0),
+                ],
+            ],
+        };
+        let gcode = render(&plot, &GcodeSettings::default());
+        let lines: Vec<&str> = gcode.lines().collect();
+
+        assert_eq!(gcode.matches("; pen down").count(), 2);
+        // The initial lift plus one after each path.
+        assert_eq!(gcode.matches("; pen up").count(), 3);
+        let second_travel = lines
+            .iter()
+            .position(|l| *l == "G0 X30.000 Y30.000")
+            .unwrap();
+        assert_eq!(lines[second_travel - 1], "G0 Z5.000 ; pen up");
+        assert_eq!(lines[second_travel + 1], "G1 Z0.000 F500 ; pen down");
+        assert_eq!(lines.last(), Some(&"M2"));
+    }
+}
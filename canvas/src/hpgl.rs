@@ -0,0 +1,60 @@
+//! HPGL for pen plotters that speak Hewlett-Packard's plotter language.
+
+use std::path::Path;
+
+use nannou::geom::Vec2;
+
+use crate::plot::Plot;
+
+/// HPGL addresses the paper in plotter units of 0.025 mm.
+const UNITS_PER_MM: f32 = 40.0;
+
+pub fn render(plot: &Plot) -> String {
+    let mut hpgl = String::from("IN;SP1;");
+    for path in &plot.paths {
+        hpgl.push_str(&format!("PU{};", units(path[0])));
+        let points: Vec<String> = path[1..].iter().map(|&p| units(p)).collect();
+        hpgl.push_str(&format!("PD{};", points.join(",")));
+    }
+    hpgl.push_str("PU;SP0;\n");
+    hpgl
+}
+
+pub fn save(plot: &Plot, path: &Path) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, render(plot))
+}
+
+fn units(p: Vec2) -> String {
+    format!(
+        "{},{}",
+        (p.x * UNITS_PER_MM).round() as i32,
+        (p.y * UNITS_PER_MM).round() as i32
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hpgl::render;
+    use crate::plot::Plot;
+    use nannou::geom::Vec2;
+
+    #[test]
+    fn paths_become_pen_up_and_pen_down_moves() {
+        let plot = Plot {
+            paper_w: 210.0,
+            paper_h: 297.0,
+            paths: vec![vec![
+                Vec2::new(10.0, 10.0),
+                Vec2::new(20.0, 10.0),
+                Vec2::new(20.0, 12.5),
+            ]],
+        };
+        assert_eq!(
+            render(&plot),
+            "IN;SP1;PU400,400;PD800,400,800,500;PU;SP0;\n"
+        );
+    }
+}
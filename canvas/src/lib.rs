@@ -0,0 +1,29 @@
+pub mod animation;
+pub mod archive;
+pub mod args;
+pub mod encode;
+pub mod export;
+pub mod gcode;
+pub mod hpgl;
+pub mod lines;
+pub mod metadata;
+pub mod occlusion;
+pub mod offline;
+pub mod optimize;
+pub mod paper;
+pub mod pdf;
+pub mod plot;
+pub mod plotter;
+pub mod print;
+pub mod raster;
+pub mod record;
+pub mod scene;
+pub mod seed;
+pub mod svg;
+#[cfg(test)]
+mod testing;
+pub mod tiles;
+pub mod units;
+pub mod verify;
+
+pub use scene::{Scene, Shape, Stroke, Units};
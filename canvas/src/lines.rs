@@ -0,0 +1,249 @@
+//! Cleans up line work where neighbouring shapes stroke the same edges.
+//!
+//! Segments are grouped by the infinite line they lie on, the overlapping and touching intervals on
+//! each line are merged, and the surviving segments are chained back into as few polylines as
+//! possible. Every edge ends up stroked exactly once.
+
+use std::collections::HashMap;
+
+use nannou::geom::Vec2;
+
+/// Segments whose directions differ by less than this many radians are treated as parallel.
+const ANGLE_TOLERANCE: f32 = 1e-4;
+
+/// A merged segment, keeping the original points at its ends so that shared vertices stay exact.
+#[derive(Copy, Clone)]
+struct Segment {
+    a: Vec2,
+    b: Vec2,
+}
+
+/// Return the smallest set of polylines that covers the same lines as `polylines`, with duplicate
+/// and overlapping segments removed and collinear runs merged.
+///
+/// `tolerance` is the distance, in the polylines' units, under which points and lines are treated
+/// as coincident.
+pub fn simplify(polylines: &[Vec<Vec2>], tolerance: f32) -> Vec<Vec<Vec2>> {
+    let segments: Vec<Segment> = polylines
+        .iter()
+        .flat_map(|line| line.windows(2).map(|w| Segment { a: w[0], b: w[1] }))
+        .filter(|s| s.a.distance(s.b) > tolerance)
+        .collect();
+    let merged = merge_collinear(segments, tolerance);
+    chain(merged, tolerance)
+}
+
+/// The line a segment lies on, as a direction angle in `[0, PI)` and a signed offset from the origin.
+fn supporting_line(segment: &Segment) -> (f32, f32) {
+    let d = segment.b - segment.a;
+    let mut angle = d.y.atan2(d.x);
+    if angle < 0.0 {
+        angle += std::f32::consts::PI;
+    }
+    if angle >= std::f32::consts::PI - ANGLE_TOLERANCE {
+        angle = 0.0;
+    }
+    let normal = Vec2::new(-angle.sin(), angle.cos());
+    (angle, normal.dot(segment.a))
+}
+
+fn merge_collinear(segments: Vec<Segment>, tolerance: f32) -> Vec<Segment> {
+    let mut keyed: Vec<(f32, f32, Segment)> = segments
+        .into_iter()
+        .map(|s| {
+            let (angle, offset) = supporting_line(&s);
+            (angle, offset, s)
+        })
+        .collect();
+    keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    let mut merged = Vec::new();
+    for same_angle in split_runs(keyed, |k| k.0, ANGLE_TOLERANCE) {
+        let mut same_angle = same_angle;
+        same_angle.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        for same_line in split_runs(same_angle, |k| k.1, tolerance) {
+            let angle = same_line[0].0;
+            let direction = Vec2::new(angle.cos(), angle.sin());
+            merged.extend(merge_intervals(
+                same_line.into_iter().map(|k| k.2).collect(),
+                direction,
+                tolerance,
+            ));
+        }
+    }
+    merged
+}
+
+/// Split a list sorted by `key` wherever consecutive keys are further apart than `tolerance`.
+fn split_runs<T, F: Fn(&T) -> f32>(sorted: Vec<T>, key: F, tolerance: f32) -> Vec<Vec<T>> {
+    let mut runs: Vec<Vec<T>> = Vec::new();
+    let mut last_key = f32::MIN;
+    for item in sorted {
+        let k = key(&item);
+        match runs.last_mut() {
+            Some(run) if k - last_key <= tolerance => run.push(item),
+            _ => runs.push(vec![item]),
+        }
+        last_key = k;
+    }
+    runs
+}
+
+/// Merge segments on one line into disjoint segments, joining any that overlap or touch.
+fn merge_intervals(segments: Vec<Segment>, direction: Vec2, tolerance: f32) -> Vec<Segment> {
+    let mut intervals: Vec<((f32, Vec2), (f32, Vec2))> = segments
+        .into_iter()
+        .map(|s| {
+            let (ta, tb) = (direction.dot(s.a), direction.dot(s.b));
+            if ta <= tb {
+                ((ta, s.a), (tb, s.b))
+            } else {
+                ((tb, s.b), (ta, s.a))
+            }
+        })
+        .collect();
+    intervals.sort_by(|a, b| a.0 .0.partial_cmp(&b.0 .0).unwrap());
+
+    let mut merged: Vec<((f32, Vec2), (f32, Vec2))> = Vec::new();
+    for interval in intervals {
+        match merged.last_mut() {
+            Some(last) if interval.0 .0 <= last.1 .0 + tolerance => {
+                if interval.1 .0 > last.1 .0 {
+                    last.1 = interval.1;
+                }
+            }
+            _ => merged.push(interval),
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(start, end)| Segment {
+            a: start.1,
+            b: end.1,
+        })
+        .collect()
+}
+
+/// Join segments that share end points into polylines, walking through every vertex where exactly
+/// two segments meet.
+fn chain(segments: Vec<Segment>, tolerance: f32) -> Vec<Vec<Vec2>> {
+    let key = |p: Vec2| {
+        (
+            (p.x / tolerance).round() as i64,
+            (p.y / tolerance).round() as i64,
+        )
+    };
+    let mut at_vertex: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    for (i, s) in segments.iter().enumerate() {
+        at_vertex.entry(key(s.a)).or_default().push(i);
+        at_vertex.entry(key(s.b)).or_default().push(i);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut polylines = Vec::new();
+    // Start at the ends and junctions first so open chains are walked whole. Whatever is left over
+    // afterwards forms closed loops.
+    let mut starts: Vec<usize> = (0..segments.len()).collect();
+    starts.sort_by_key(|&i| {
+        let degree_a = at_vertex[&key(segments[i].a)].len();
+        let degree_b = at_vertex[&key(segments[i].b)].len();
+        degree_a == 2 && degree_b == 2
+    });
+    for start in starts {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let s = segments[start];
+        let (first, second) = if at_vertex[&key(s.a)].len() == 2 && at_vertex[&key(s.b)].len() != 2
+        {
+            (s.b, s.a)
+        } else {
+            (s.a, s.b)
+        };
+        let mut polyline = vec![first, second];
+        let mut end = second;
+        loop {
+            let candidates = &at_vertex[&key(end)];
+            if candidates.len() != 2 {
+                break;
+            }
+            let next = match candidates.iter().find(|&&i| !used[i]) {
+                Some(&next) => next,
+                None => break,
+            };
+            used[next] = true;
+            let n = segments[next];
+            end = if key(n.a) == key(end) { n.b } else { n.a };
+            polyline.push(end);
+        }
+        polylines.push(polyline);
+    }
+    polylines
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lines::simplify;
+    use nannou::geom::Vec2;
+
+    fn total_length(polylines: &[Vec<Vec2>]) -> f32 {
+        polylines
+            .iter()
+            .map(|l| l.windows(2).map(|w| w[0].distance(w[1])).sum::<f32>())
+            .sum()
+    }
+
+    #[test]
+    fn collinear_runs_and_overlaps_merge() {
+        let lines = vec![
+            vec![Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0)],
+            vec![Vec2::new(1.0, 0.0), Vec2::new(2.0, 0.0)],
+            vec![Vec2::new(3.0, 0.0), Vec2::new(1.5, 0.0)],
+        ];
+        let simplified = simplify(&lines, 1e-3);
+        assert_eq!(simplified.len(), 1);
+        assert_eq!(simplified[0].len(), 2);
+        assert!((total_length(&simplified) - 3.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn shared_triangle_edges_are_drawn_once() {
+        // Two triangles splitting a unit square along its diagonal, each drawn as a closed outline.
+        let (a, b, c, d) = (
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(0.0, 1.0),
+        );
+        let lines = vec![vec![a, b, c, a], vec![a, c, d, a]];
+        let simplified = simplify(&lines, 1e-3);
+
+        assert!((total_length(&lines) - (4.0 + 2.0 * 2f32.sqrt())).abs() < 1e-4);
+        assert!((total_length(&simplified) - (4.0 + 2f32.sqrt())).abs() < 1e-4);
+    }
+
+    #[test]
+    fn subdivided_grid_collapses_to_long_lines() {
+        // A 4x4 grid of unit squares, each drawn as its own closed outline.
+        let mut lines = Vec::new();
+        for x in 0..4 {
+            for y in 0..4 {
+                let (x, y) = (x as f32, y as f32);
+                lines.push(vec![
+                    Vec2::new(x, y),
+                    Vec2::new(x + 1.0, y),
+                    Vec2::new(x + 1.0, y + 1.0),
+                    Vec2::new(x, y + 1.0),
+                    Vec2::new(x, y),
+                ]);
+            }
+        }
+        let simplified = simplify(&lines, 1e-3);
+
+        // 5 horizontal and 5 vertical lines of length 4.
+        assert!((total_length(&simplified) - 40.0).abs() < 1e-3);
+        let segments: usize = simplified.iter().map(|l| l.len() - 1).sum();
+        assert_eq!(segments, 10);
+    }
+}
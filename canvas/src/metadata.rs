@@ -0,0 +1,237 @@
+//! Records what produced an image in the PNG itself, so that a render worth keeping can be made
+//! again from nothing but the file.
+//!
+//! Entries are written as `tEXt` chunks, or `iTXt` when the text is not plain ASCII, straight after
+//! the image header. The `replay` tool reads them back and runs the sketch again with the same
+//! seed and options.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::args::Args;
+use crate::seed;
+
+pub(crate) const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+pub const SKETCH: &str = "Sketch";
+pub const VERSION: &str = "Version";
+pub const SEED: &str = "Seed";
+/// The command line the sketch was run with, as a JSON array of arguments.
+pub const PARAMETERS: &str = "Parameters";
+/// The number of a recorded frame, counting from 0. Single images have none.
+pub const FRAME: &str = "Frame";
+/// The pixel size the image was rendered at, as `WIDTHxHEIGHT` like `--size`.
+pub const SIZE: &str = "Size";
+
+/// Key and value pairs describing how an image was made, in the order they were added.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    entries: Vec<(String, String)>,
+}
+
+impl Metadata {
+    /// The entries every sketch records: its name, its crate version, the seed and the command line.
+    /// Pass `env!("CARGO_PKG_NAME")` and `env!("CARGO_PKG_VERSION")`.
+    ///
+    /// A model loaded with `--load-model` was not generated from this run's seed, so none is
+    /// recorded then. The command line names the model instead.
+    pub fn new(sketch: &str, version: &str) -> Self {
+        let arguments: Vec<String> = std::env::args().skip(1).collect();
+        let metadata = Metadata::default()
+            .with(SKETCH, sketch)
+            .with(VERSION, version);
+        let metadata = if Args::from_env().value("load-model").is_some() {
+            metadata
+        } else {
+            metadata.with(SEED, seed::master())
+        };
+        metadata.with(
+            PARAMETERS,
+            serde_json::to_string(&arguments).expect("arguments are always valid JSON"),
+        )
+    }
+
+    /// Add an entry. Keys are PNG keywords, 1 to 79 printable Latin-1 characters.
+    pub fn with<V: ToString>(mut self, key: &str, value: V) -> Self {
+        self.entries.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.entries
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The recorded command line, if there is one and it parses.
+    pub fn parameters(&self) -> Option<Vec<String>> {
+        serde_json::from_str(self.get(PARAMETERS)?).ok()
+    }
+
+    /// Write the entries into the PNG at `path`, after its header.
+    pub fn stamp(&self, path: &Path) -> io::Result<()> {
+        let png = fs::read(path)?;
+        fs::write(path, self.stamped(&png)?)
+    }
+
+    /// A copy of the encoded `png` with the entries written after its header.
+    pub fn stamped(&self, png: &[u8]) -> io::Result<Vec<u8>> {
+        let header_end = chunks(png)?
+            .first()
+            .filter(|chunk| &chunk.kind == b"IHDR")
+            .map(|chunk| chunk.end)
+            .ok_or_else(|| invalid("PNG does not start with a header"))?;
+        let mut stamped = png[..header_end].to_vec();
+        for (key, value) in &self.entries {
+            write_text(&mut stamped, key, value);
+        }
+        stamped.extend_from_slice(&png[header_end..]);
+        Ok(stamped)
+    }
+
+    /// Read the text entries back out of the PNG at `path`.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let png = fs::read(path)?;
+        let mut metadata = Metadata::default();
+        for chunk in chunks(&png)? {
+            let data = &png[chunk.data.clone()];
+            let text = match &chunk.kind {
+                b"tEXt" => read_text(data),
+                b"iTXt" => read_international_text(data),
+                _ => continue,
+            };
+            if let Some((key, value)) = text {
+                metadata.entries.push((key, value));
+            }
+        }
+        Ok(metadata)
+    }
+}
+
+/// A weighted palette as `#rrggbb weight` pairs, such as `#45868f 0.4, #fa7a7a 0.2`.
+pub fn palette(colors: &[((u8, u8, u8), f32)]) -> String {
+    colors
+        .iter()
+        .map(|((r, g, b), weight)| format!("#{:02x}{:02x}{:02x} {}", r, g, b, weight))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub(crate) struct Chunk {
+    pub kind: [u8; 4],
+    pub data: std::ops::Range<usize>,
+    /// Where the chunk, including its CRC, ends.
+    pub end: usize,
+}
+
+pub(crate) fn chunks(png: &[u8]) -> io::Result<Vec<Chunk>> {
+    if !png.starts_with(&SIGNATURE) {
+        return Err(invalid("not a PNG"));
+    }
+    let mut chunks = Vec::new();
+    let mut at = SIGNATURE.len();
+    while at + 8 <= png.len() {
+        let length = u32::from_be_bytes(png[at..at + 4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = png[at + 4..at + 8].try_into().unwrap();
+        let data = at + 8..at + 8 + length;
+        let end = data.end + 4;
+        if end > png.len() {
+            return Err(invalid("PNG chunk runs past the end of the file"));
+        }
+        chunks.push(Chunk { kind, data, end });
+        at = end;
+        if &kind == b"IEND" {
+            break;
+        }
+    }
+    Ok(chunks)
+}
+
+fn write_text(png: &mut Vec<u8>, key: &str, value: &str) {
+    let mut data = key.as_bytes().to_vec();
+    data.push(0);
+    if value.is_ascii() {
+        data.extend_from_slice(value.as_bytes());
+        write_chunk(png, b"tEXt", &data);
+    } else {
+        // Uncompressed, with empty language and translated keyword.
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(value.as_bytes());
+        write_chunk(png, b"iTXt", &data);
+    }
+}
+
+pub(crate) fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+fn read_text(data: &[u8]) -> Option<(String, String)> {
+    let split = data.iter().position(|&b| b == 0)?;
+    // tEXt is Latin-1, which maps one to one onto the first 256 code points.
+    let latin1 = |bytes: &[u8]| bytes.iter().map(|&b| b as char).collect::<String>();
+    Some((latin1(&data[..split]), latin1(&data[split + 1..])))
+}
+
+fn read_international_text(data: &[u8]) -> Option<(String, String)> {
+    let split = data.iter().position(|&b| b == 0)?;
+    let key = String::from_utf8_lossy(&data[..split]).into_owned();
+    let rest = data.get(split + 1..)?;
+    // Compressed text is never written here, so it is skipped rather than inflated.
+    if rest.first() != Some(&0) {
+        return None;
+    }
+    let rest = rest.get(2..)?;
+    let language_end = rest.iter().position(|&b| b == 0)?;
+    let rest = &rest[language_end + 1..];
+    let translated_end = rest.iter().position(|&b| b == 0)?;
+    let text = String::from_utf8(rest[translated_end + 1..].to_vec()).ok()?;
+    Some((key, text))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metadata::{palette, Metadata, PARAMETERS, SEED, SKETCH};
+    use crate::testing::TempDir;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn entries_survive_a_round_trip_through_a_png() {
+        let dir = TempDir::new("metadata");
+        let path = dir.join("stamped.png");
+        let image = RgbaImage::from_pixel(3, 2, Rgba([10, 20, 30, 255]));
+        image.save(&path).unwrap();
+
+        let metadata = Metadata::default()
+            .with(SKETCH, "cells")
+            .with(SEED, 1234)
+            .with(PARAMETERS, r#"["--paper","A3"]"#)
+            .with("Palette", palette(&[((0x45, 0x86, 0x8f), 0.4)]))
+            .with("Title", "Zellen über Papier");
+        metadata.stamp(&path).unwrap();
+
+        assert_eq!(Metadata::read(&path).unwrap(), metadata);
+        assert_eq!(metadata.get("Palette"), Some("#45868f 0.4"));
+        assert_eq!(
+            metadata.parameters(),
+            Some(vec!["--paper".to_string(), "A3".to_string()])
+        );
+        // The image itself is untouched and still decodes.
+        assert_eq!(image::open(&path).unwrap().to_rgba8(), image);
+    }
+}
@@ -0,0 +1,343 @@
+//! Hidden-line removal for vector and plotter output.
+//!
+//! A raster paints shapes in order, so an opaque fill hides whatever was drawn under it. A pen
+//! plotter draws every line, so each line is cut against the opaque fills drawn after it and only
+//! the parts left showing are kept. Translucent fills hide nothing.
+
+use nannou::geom::{Rect, Vec2};
+
+use crate::plot::{bounds, pen_path};
+use crate::scene::{Scene, Shape};
+
+/// Points closer than this to an occluder's edge count as outside it, so that a line running along
+/// the edge of a later fill stays visible, as half of its stroke does in the raster.
+const EDGE_TOLERANCE: f32 = 1e-4;
+
+/// An opaque fill, as a closed outline, and the index of the shape it came from.
+struct Occluder {
+    index: usize,
+    ring: Vec<Vec2>,
+    bounds: Rect,
+}
+
+/// Every line the pen draws, as in `plot::pen_paths`, with the parts hidden under later opaque
+/// fills removed.
+pub fn visible_paths(scene: &Scene) -> Vec<Vec<Vec2>> {
+    let occluders = occluders(scene);
+    scene
+        .shapes
+        .iter()
+        .enumerate()
+        .flat_map(|(i, shape)| visible(pen_path(shape), i, &occluders))
+        .filter(|path| path.len() > 1)
+        .collect()
+}
+
+/// The scene with hidden lines removed. Strokes are cut into the polylines left showing, and fills
+/// are kept as they are since the raster and SVG paint over them anyway.
+pub fn occlude(scene: &Scene) -> Scene {
+    let occluders = occluders(scene);
+    let mut shapes = Vec::with_capacity(scene.shapes.len());
+    for (i, shape) in scene.shapes.iter().enumerate() {
+        match shape {
+            Shape::Polyline { points, stroke } => {
+                for points in visible(points.clone(), i, &occluders) {
+                    shapes.push(Shape::Polyline {
+                        points,
+                        stroke: *stroke,
+                    });
+                }
+            }
+            Shape::Polygon {
+                points,
+                fill,
+                stroke: Some(stroke),
+            } => {
+                if fill.is_some() {
+                    shapes.push(Shape::Polygon {
+                        points: points.clone(),
+                        fill: *fill,
+                        stroke: None,
+                    });
+                }
+                for points in visible(pen_path(shape), i, &occluders) {
+                    shapes.push(Shape::Polyline {
+                        points,
+                        stroke: *stroke,
+                    });
+                }
+            }
+            _ => shapes.push(shape.clone()),
+        }
+    }
+    Scene {
+        rect: scene.rect,
+        units: scene.units,
+        background: scene.background,
+        shapes,
+    }
+}
+
+fn occluders(scene: &Scene) -> Vec<Occluder> {
+    scene
+        .shapes
+        .iter()
+        .enumerate()
+        .filter(|(_, shape)| match shape {
+            Shape::Polygon {
+                fill: Some(fill), ..
+            }
+            | Shape::Ellipse { fill, .. } => fill.alpha == 255,
+            _ => false,
+        })
+        .filter_map(|(index, shape)| {
+            let ring = pen_path(shape);
+            let bounds = bounds(std::slice::from_ref(&ring))?;
+            (ring.len() > 3).then_some(Occluder {
+                index,
+                ring,
+                bounds,
+            })
+        })
+        .collect()
+}
+
+/// The parts of `path`, drawn as shape `index`, that no later occluder covers.
+fn visible(path: Vec<Vec2>, index: usize, occluders: &[Occluder]) -> Vec<Vec<Vec2>> {
+    let path_bounds = match bounds(std::slice::from_ref(&path)) {
+        Some(bounds) => bounds,
+        None => return Vec::new(),
+    };
+    let mut pieces = vec![path];
+    for occluder in occluders {
+        if occluder.index <= index || !overlaps(&occluder.bounds, &path_bounds) {
+            continue;
+        }
+        pieces = pieces
+            .iter()
+            .flat_map(|piece| outside(piece, &occluder.ring))
+            .collect();
+        if pieces.is_empty() {
+            break;
+        }
+    }
+    pieces
+}
+
+fn overlaps(a: &Rect, b: &Rect) -> bool {
+    a.left() <= b.right() && b.left() <= a.right() && a.bottom() <= b.top() && b.bottom() <= a.top()
+}
+
+/// Cut `path` wherever it crosses the closed `ring`, keeping the parts outside it.
+fn outside(path: &[Vec2], ring: &[Vec2]) -> Vec<Vec<Vec2>> {
+    let mut kept: Vec<Vec<Vec2>> = Vec::new();
+    let mut current: Vec<Vec2> = Vec::new();
+    for segment in path.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+        let mut ts = vec![0.0, 1.0];
+        ts.extend(
+            ring.windows(2)
+                .filter_map(|edge| crossing(a, b, edge[0], edge[1])),
+        );
+        ts.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        // The ends are taken as they are, as `a + (b - a)` need not come back to exactly `b`
+        // and the next segment has to carry on from the same point.
+        let along = |t: f32| match t {
+            0.0 => a,
+            1.0 => b,
+            _ => a + (b - a) * t,
+        };
+        for t in ts.windows(2) {
+            if t[1] - t[0] <= f32::EPSILON {
+                continue;
+            }
+            let (start, end) = (along(t[0]), along(t[1]));
+            if contains(ring, (start + end) * 0.5) {
+                if current.len() > 1 {
+                    kept.push(std::mem::take(&mut current));
+                }
+                current.clear();
+            } else {
+                if current.last() != Some(&start) {
+                    if current.len() > 1 {
+                        kept.push(std::mem::take(&mut current));
+                    }
+                    current = vec![start];
+                }
+                current.push(end);
+            }
+        }
+    }
+    if current.len() > 1 {
+        kept.push(current);
+    }
+    kept
+}
+
+/// Where along `a`-`b` it crosses the edge `c`-`d`, as a fraction of its length.
+fn crossing(a: Vec2, b: Vec2, c: Vec2, d: Vec2) -> Option<f32> {
+    let (r, s) = (b - a, d - c);
+    let denominator = r.perp_dot(s);
+    if denominator == 0.0 {
+        return None;
+    }
+    let t = (c - a).perp_dot(s) / denominator;
+    let u = (c - a).perp_dot(r) / denominator;
+    ((0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u)).then_some(t)
+}
+
+/// Even-odd test for whether `p` lies strictly inside the closed `ring`.
+fn contains(ring: &[Vec2], p: Vec2) -> bool {
+    let mut inside = false;
+    for edge in ring.windows(2) {
+        let (c, d) = (edge[0], edge[1]);
+        if distance_to_segment(p, c, d) < EDGE_TOLERANCE {
+            return false;
+        }
+        if (c.y > p.y) != (d.y > p.y) && p.x < c.x + (p.y - c.y) / (d.y - c.y) * (d.x - c.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+fn distance_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = ((p - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0);
+    if t.is_nan() {
+        return p.distance(a);
+    }
+    p.distance(a + ab * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::occlusion::{occlude, outside, visible_paths};
+    use crate::scene::{Scene, Shape};
+    use nannou::color::{Srgb, Srgba};
+    use nannou::geom::{Rect, Vec2};
+
+    fn length(paths: &[Vec<Vec2>]) -> f32 {
+        paths
+            .iter()
+            .map(|l| l.windows(2).map(|w| w[0].distance(w[1])).sum::<f32>())
+            .sum()
+    }
+
+    fn scene() -> Scene {
+        Scene::new(Rect::from_w_h(100.0, 100.0), Srgb::new(255u8, 255, 255))
+    }
+
+    #[test]
+    fn later_fills_hide_earlier_lines() {
+        let mut scene = scene();
+        let black = Srgb::new(0u8, 0, 0);
+        scene.polyline(
+            vec![Vec2::new(-40.0, 0.0), Vec2::new(40.0, 0.0)],
+            black,
+            1.0,
+        );
+        scene.rect(
+            &Rect::from_x_y_w_h(0.0, 0.0, 20.0, 20.0),
+            Srgb::new(255u8, 0, 0),
+        );
+        // Drawn after the rect, so it shows on top of it.
+        scene.polyline(
+            vec![Vec2::new(0.0, -40.0), Vec2::new(0.0, 40.0)],
+            black,
+            1.0,
+        );
+
+        let paths = visible_paths(&scene);
+        // The horizontal line loses the 20 units under the rect, the vertical line and the rect's
+        // own outline are whole.
+        assert_eq!(paths.len(), 4);
+        assert!((length(&paths) - (60.0 + 80.0 + 80.0)).abs() < 1e-3);
+        assert_eq!(paths[0], vec![Vec2::new(-40.0, 0.0), Vec2::new(-10.0, 0.0)]);
+        assert_eq!(paths[1], vec![Vec2::new(10.0, 0.0), Vec2::new(40.0, 0.0)]);
+    }
+
+    #[test]
+    fn overlapping_fills_hide_each_others_outlines() {
+        let mut scene = scene();
+        scene.rect(
+            &Rect::from_x_y_w_h(0.0, 0.0, 20.0, 20.0),
+            Srgb::new(255u8, 0, 0),
+        );
+        scene.rect(
+            &Rect::from_x_y_w_h(10.0, 10.0, 20.0, 20.0),
+            Srgb::new(0u8, 0, 255),
+        );
+        // Translucent fills hide nothing.
+        scene.rect(
+            &Rect::from_x_y_w_h(0.0, 0.0, 50.0, 50.0),
+            Srgba::new(0u8, 255, 0, 128),
+        );
+
+        let paths = visible_paths(&scene);
+        // The first square loses the 10 x 10 corner under the second.
+        assert!((length(&paths) - (60.0 + 80.0 + 200.0)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn lines_along_a_fill_edge_stay_visible() {
+        let mut scene = scene();
+        scene.rect_outline(
+            &Rect::from_x_y_w_h(0.0, 0.0, 20.0, 20.0),
+            Srgb::new(0u8, 0, 0),
+            1.0,
+        );
+        scene.rect(
+            &Rect::from_x_y_w_h(0.0, 0.0, 20.0, 20.0),
+            Srgb::new(255u8, 0, 0),
+        );
+        scene.polyline(
+            vec![Vec2::new(-5.0, -5.0), Vec2::new(5.0, 5.0)],
+            Srgb::new(0u8, 0, 0),
+            1.0,
+        );
+        scene.ellipse(Vec2::new(0.0, 0.0), 1.0, Srgb::new(0u8, 0, 0));
+
+        let occluded = occlude(&scene);
+        let lines: Vec<Vec<Vec2>> = occluded
+            .shapes
+            .iter()
+            .filter_map(|shape| match shape {
+                Shape::Polyline { points, .. } => Some(points.clone()),
+                _ => None,
+            })
+            .collect();
+        // The outline became a polyline left whole, and the diagonal lost its middle to the dot.
+        assert!((length(&lines[..1]) - 80.0).abs() < 1e-3);
+        assert_eq!(lines.len(), 3);
+        assert!(matches!(
+            occluded.shapes.last(),
+            Some(Shape::Ellipse { .. })
+        ));
+    }
+
+    #[test]
+    fn cut_paths_stay_whole_between_their_cuts() {
+        // Coordinates that floating point cannot hold exactly, with the last segment ending
+        // inside the square.
+        let path = [
+            Vec2::new(-33.3, 0.7),
+            Vec2::new(-21.1, 7.9),
+            Vec2::new(-9.7, -3.1),
+            Vec2::new(1.3, 0.3),
+            Vec2::new(12.9, 0.3),
+        ];
+        let ring = [
+            Vec2::new(6.7, -3.3),
+            Vec2::new(13.3, -3.3),
+            Vec2::new(13.3, 3.3),
+            Vec2::new(6.7, 3.3),
+            Vec2::new(6.7, -3.3),
+        ];
+
+        let kept = outside(&path, &ring);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0][..4], path[..4]);
+        assert!((kept[0][4] - Vec2::new(6.7, 0.3)).length() < 1e-4);
+    }
+}
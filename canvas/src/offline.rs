@@ -0,0 +1,261 @@
+//! Renders a set number of frames without a window, as fast as the machine allows, stepping the
+//! sketch by a fixed amount of simulated time each frame instead of however long the last one took.
+//!
+//! `--offline 600` renders 600 frames and records them, see `record`. `--timestep 1/60` sets the
+//! step, as a fraction or a decimal number of seconds, and by default it is one frame at `--fps`
+//! so that an animation plays back at the speed it was simulated. Frame `n` always sees exactly
+//! `n` steps of time, so two runs with the same seed produce the same frames.
+
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use nannou::event::Update;
+
+use crate::animation::DEFAULT_FPS;
+use crate::args::Args;
+use crate::record::Recording;
+use crate::scene::Scene;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+/// A step of simulated time as a fraction of a second, kept exact so that steps never drift.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Timestep {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl Timestep {
+    /// One frame at `fps` frames per second.
+    pub fn per_second(fps: u16) -> Self {
+        Timestep {
+            numerator: 1,
+            denominator: fps.max(1) as u64,
+        }
+    }
+
+    /// The time `steps` steps take, rounded to the nanosecond.
+    pub fn times(&self, steps: u64) -> Duration {
+        let nanos =
+            steps as u128 * self.numerator as u128 * NANOS_PER_SECOND / self.denominator as u128;
+        Duration::from_nanos(nanos as u64)
+    }
+}
+
+impl fmt::Display for Timestep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{} s", self.numerator, self.denominator)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TimestepParseErr {
+    BadNumberErr(String),
+    ZeroStepErr,
+}
+
+impl fmt::Display for TimestepParseErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimestepParseErr::BadNumberErr(s) => {
+                write!(f, "{:?} is not a number of seconds like 1/60 or 0.02", s)
+            }
+            TimestepParseErr::ZeroStepErr => write!(f, "a timestep has to be longer than zero"),
+        }
+    }
+}
+
+impl std::error::Error for TimestepParseErr {}
+
+impl FromStr for Timestep {
+    type Err = TimestepParseErr;
+
+    /// Parse `1/60`, `0.02` or `2`, in seconds.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || TimestepParseErr::BadNumberErr(s.to_string());
+        let number = |n: &str| n.trim().parse::<u64>().map_err(|_| bad());
+        let (numerator, denominator) = match s.split_once('/') {
+            Some((numerator, denominator)) => (number(numerator)?, number(denominator)?),
+            None => match s.split_once('.') {
+                Some((whole, fraction)) => {
+                    if fraction.is_empty() || fraction.len() > 9 {
+                        return Err(bad());
+                    }
+                    let denominator = 10u64.pow(fraction.len() as u32);
+                    let whole = if whole.is_empty() { 0 } else { number(whole)? };
+                    (whole * denominator + number(fraction)?, denominator)
+                }
+                None => (number(s)?, 1),
+            },
+        };
+        if numerator == 0 || denominator == 0 {
+            return Err(TimestepParseErr::ZeroStepErr);
+        }
+        Ok(Timestep {
+            numerator,
+            denominator,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Offline {
+    pub frames: u64,
+    pub timestep: Timestep,
+}
+
+impl Offline {
+    /// Read `--offline` and `--timestep`, or `None` if the sketch should open a window as usual.
+    pub fn from_args(args: &Args) -> Option<Self> {
+        if !args.flag("offline") {
+            return None;
+        }
+        let frames = match args.parsed::<u64>("offline") {
+            Some(frames) if frames > 0 => frames,
+            _ => {
+                eprintln!("--offline needs the number of frames to render, such as --offline 600");
+                std::process::exit(2);
+            }
+        };
+        let timestep = args
+            .parsed("timestep")
+            .unwrap_or_else(|| Timestep::per_second(args.parsed("fps").unwrap_or(DEFAULT_FPS)));
+        Some(Offline { frames, timestep })
+    }
+
+    /// What the sketch's update sees before frame `nth`: no time at all for the first frame, then
+    /// one step per frame.
+    pub fn update(&self, nth: u64) -> Update {
+        let since_start = self.timestep.times(nth);
+        Update {
+            since_last: since_start - self.timestep.times(nth.saturating_sub(1)),
+            since_start,
+        }
+    }
+
+    /// Step `model` through every frame with `update`, recording the ones `recording` asks for
+    /// rendered from `scene` at `width` by `height` pixels. Frames a resumed recording already has
+    /// are still simulated, so the ones after them come out the same.
+    pub fn run<M>(
+        &self,
+        model: &mut M,
+        recording: &Recording,
+        (width, height): (u32, u32),
+        mut update: impl FnMut(&mut M, Update),
+        scene: impl Fn(&M) -> Scene,
+    ) -> io::Result<()> {
+        let started = Instant::now();
+        for nth in 0..self.frames {
+            update(model, self.update(nth));
+            if recording.records(nth) {
+                recording.add_scene_at(nth, scene(model), width, height)?;
+            }
+        }
+        recording.finish()?;
+        println!(
+            "Rendered {} frames, {:.2} s at {}, in {:.2} s",
+            self.frames,
+            self.timestep.times(self.frames).as_secs_f64(),
+            self.timestep,
+            started.elapsed().as_secs_f64()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::Args;
+    use crate::metadata::Metadata;
+    use crate::offline::{Offline, Timestep, TimestepParseErr};
+    use crate::record::{FrameRange, Recording};
+    use crate::scene::Scene;
+    use crate::testing::TempDir;
+    use nannou::color::Srgb;
+    use nannou::geom::{Rect, Vec2};
+    use std::time::Duration;
+
+    #[test]
+    fn timesteps_parse_as_exact_fractions() {
+        let sixtieth: Timestep = "1/60".parse().unwrap();
+        assert_eq!(sixtieth, Timestep::per_second(60));
+        let fiftieth: Timestep = "0.02".parse().unwrap();
+        assert_eq!((fiftieth.numerator, fiftieth.denominator), (2, 100));
+        assert_eq!(
+            "2".parse::<Timestep>().unwrap().times(3),
+            Duration::from_secs(6)
+        );
+        assert_eq!("0".parse::<Timestep>(), Err(TimestepParseErr::ZeroStepErr));
+        assert!(matches!(
+            "1/x".parse::<Timestep>(),
+            Err(TimestepParseErr::BadNumberErr(_))
+        ));
+    }
+
+    #[test]
+    fn updates_step_by_the_timestep_without_drifting() {
+        let args = Args::parse(["--offline", "600", "--timestep", "1/60"].map(String::from));
+        let offline = Offline::from_args(&args).unwrap();
+        assert_eq!(offline.frames, 600);
+        assert_eq!(offline.update(0).since_start, Duration::ZERO);
+        assert_eq!(offline.update(0).since_last, Duration::ZERO);
+        assert_eq!(offline.update(60).since_start, Duration::from_secs(1));
+        assert_eq!(
+            offline.update(599).since_start,
+            Duration::from_nanos(9_983_333_333)
+        );
+        // Steps round to the nanosecond one way or the other, but always add up to the total.
+        let total: Duration = (0..=600).map(|nth| offline.update(nth).since_last).sum();
+        assert_eq!(total, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn runs_with_the_same_timestep_record_the_same_frames() {
+        let offline = Offline {
+            frames: 4,
+            timestep: Timestep::per_second(10),
+        };
+        // A dot that moves with the time since the start.
+        let scene = |seconds: &f32| {
+            let mut scene = Scene::new(Rect::from_w_h(20.0, 20.0), Srgb::new(255u8, 255, 255));
+            scene.ellipse(
+                Vec2::new(*seconds * 20.0 - 5.0, 0.0),
+                2.5,
+                Srgb::new(0u8, 0, 0),
+            );
+            scene
+        };
+        let run = || {
+            let dir = TempDir::new("offline");
+            let recording = Recording::from_args(
+                &Args::parse(Vec::new()),
+                dir.join("frames"),
+                Some(FrameRange::first(offline.frames)),
+                Metadata::default(),
+                Vec::new(),
+            )
+            .unwrap();
+            let mut seconds = 0.0f32;
+            offline
+                .run(
+                    &mut seconds,
+                    &recording,
+                    (40, 40),
+                    |seconds, update| *seconds = update.since_start.as_secs_f32(),
+                    scene,
+                )
+                .unwrap();
+            let frames: Vec<Vec<u8>> = (0..offline.frames)
+                .map(|nth| std::fs::read(recording.frame_path(nth)).unwrap())
+                .collect();
+            frames
+        };
+
+        let (first, second) = (run(), run());
+        assert_eq!(first, second);
+        // The dot did move, so the frames are not all the same image.
+        assert_ne!(first[0], first[3]);
+    }
+}
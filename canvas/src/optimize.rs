@@ -0,0 +1,401 @@
+//! Reorders pen paths so a plotter spends less time travelling with the pen up.
+//!
+//! Paths are visited greedily from where the pen starts, always moving to the nearest remaining
+//! path end, and may be drawn backwards when that end is closer. The ends are kept in a grid of
+//! buckets so finding the nearest one only looks around the pen rather than at every path. Paths that meet end to start are then joined into one, and an
+//! optional 2-opt pass reverses runs of paths while that keeps shortening the travel.
+
+use nannou::geom::Vec2;
+
+/// Ends closer than this are treated as touching when joining paths.
+const JOIN_TOLERANCE: f32 = 1e-3;
+/// Upper bound on 2-opt sweeps over the whole path list.
+const MAX_TWO_OPT_PASSES: usize = 8;
+
+#[derive(Copy, Clone, Debug)]
+pub struct OptimizeOptions {
+    /// Allow drawing a path from its last point to its first.
+    pub reverse: bool,
+    /// Merge consecutive paths whose ends touch into a single pen-down stroke.
+    pub join: bool,
+    /// Follow the greedy ordering with 2-opt improvement. Needs `reverse`.
+    pub two_opt: bool,
+    /// Where the pen is before the first path, such as the plotter's home, in path coordinates.
+    pub start: Vec2,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        OptimizeOptions {
+            reverse: true,
+            join: true,
+            two_opt: false,
+            start: Vec2::ZERO,
+        }
+    }
+}
+
+/// Pen distances for drawing a list of paths in order, starting from a given point.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PathStats {
+    pub paths: usize,
+    pub pen_down: f32,
+    pub pen_up: f32,
+}
+
+impl PathStats {
+    pub fn of(paths: &[Vec<Vec2>], start: Vec2) -> Self {
+        let pen_down = paths
+            .iter()
+            .map(|path| path.windows(2).map(|w| w[0].distance(w[1])).sum::<f32>())
+            .sum();
+        let mut position = start;
+        let mut pen_up = 0.0;
+        for path in paths {
+            pen_up += position.distance(path[0]);
+            position = path[path.len() - 1];
+        }
+        PathStats {
+            paths: paths.len(),
+            pen_down,
+            pen_up,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct OptimizeReport {
+    pub before: PathStats,
+    pub after: PathStats,
+}
+
+impl std::fmt::Display for OptimizeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} paths, pen down {:.1}, pen up {:.1} -> {} paths, pen down {:.1}, pen up {:.1}",
+            self.before.paths,
+            self.before.pen_down,
+            self.before.pen_up,
+            self.after.paths,
+            self.after.pen_down,
+            self.after.pen_up
+        )
+    }
+}
+
+pub fn optimize(
+    paths: Vec<Vec<Vec2>>,
+    options: &OptimizeOptions,
+) -> (Vec<Vec<Vec2>>, OptimizeReport) {
+    let paths: Vec<Vec<Vec2>> = paths.into_iter().filter(|p| !p.is_empty()).collect();
+    let before = PathStats::of(&paths, options.start);
+
+    let mut ordered = nearest_neighbour_order(paths, options.start, options.reverse);
+    if options.two_opt && options.reverse {
+        two_opt(&mut ordered, options.start);
+    }
+    if options.join {
+        ordered = join_touching(ordered);
+    }
+
+    let after = PathStats::of(&ordered, options.start);
+    (ordered, OptimizeReport { before, after })
+}
+
+fn nearest_neighbour_order(paths: Vec<Vec<Vec2>>, start: Vec2, reverse: bool) -> Vec<Vec<Vec2>> {
+    let mut ends = EndGrid::new(&paths, reverse);
+    let mut paths: Vec<Option<Vec<Vec2>>> = paths.into_iter().map(Some).collect();
+    let mut ordered = Vec::with_capacity(paths.len());
+    let mut position = start;
+    while let Some((index, reversed)) = ends.nearest(position) {
+        ends.remove(index);
+        let mut path = paths[index].take().unwrap();
+        if reversed {
+            path.reverse();
+        }
+        position = path[path.len() - 1];
+        ordered.push(path);
+    }
+    ordered
+}
+
+/// The ends of every path not drawn yet, bucketed into square cells over their bounding box.
+struct EndGrid {
+    min: Vec2,
+    cell: f32,
+    columns: usize,
+    rows: usize,
+    /// The ends in each cell, row by row, as the path index, whether it is the path's last point
+    /// and the point itself.
+    cells: Vec<Vec<(usize, bool, Vec2)>>,
+    /// The cells holding each path's ends.
+    homes: Vec<[usize; 2]>,
+}
+
+impl EndGrid {
+    fn new(paths: &[Vec<Vec2>], reverse: bool) -> Self {
+        let ends: Vec<(usize, bool, Vec2)> = paths
+            .iter()
+            .enumerate()
+            .flat_map(|(i, path)| {
+                let last = Some((i, true, path[path.len() - 1])).filter(|_| reverse);
+                std::iter::once((i, false, path[0])).chain(last)
+            })
+            .collect();
+        let (min, max) = ends.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), &(_, _, p)| (min.min(p), max.max(p)),
+        );
+        // About one end per cell, without letting a long thin spread of ends make the cells tiny.
+        let size = (max - min).max(Vec2::ZERO);
+        let count = ends.len().max(1) as f32;
+        let cell = (size.x * size.y / count)
+            .sqrt()
+            .max(size.x.max(size.y) / count)
+            .max(JOIN_TOLERANCE);
+        let columns = (size.x / cell) as usize + 1;
+        let rows = (size.y / cell) as usize + 1;
+
+        let mut grid = EndGrid {
+            min,
+            cell,
+            columns,
+            rows,
+            cells: vec![Vec::new(); columns * rows],
+            homes: vec![[0, 0]; paths.len()],
+        };
+        for (i, last, p) in ends {
+            let (column, row) = grid.locate(p);
+            let home = row * columns + column;
+            grid.cells[home].push((i, last, p));
+            grid.homes[i][last as usize] = home;
+        }
+        grid
+    }
+
+    /// The cell `p` falls in, or the nearest one if it is outside the grid.
+    fn locate(&self, p: Vec2) -> (usize, usize) {
+        let cell = ((p - self.min) / self.cell).max(Vec2::ZERO);
+        (
+            (cell.x as usize).min(self.columns - 1),
+            (cell.y as usize).min(self.rows - 1),
+        )
+    }
+
+    /// The path with the end nearest to `position`, and whether that end is its last point. Rings
+    /// of cells are searched outwards until no unsearched cell can be nearer than the best so far.
+    /// Ties go to the lowest path index and then to its first point, so the order is repeatable.
+    fn nearest(&self, position: Vec2) -> Option<(usize, bool)> {
+        let (column, row) = self.locate(position);
+        let mut best: Option<(f32, usize, bool)> = None;
+        for ring in 0..self.columns.max(self.rows) {
+            let ring_cells =
+                ring_cells(column, row, ring).filter(|&(x, y)| x < self.columns && y < self.rows);
+            for (x, y) in ring_cells {
+                for &(i, last, p) in &self.cells[y * self.columns + x] {
+                    let candidate = (position.distance_squared(p), i, last);
+                    if best.is_none_or(|best| candidate < best) {
+                        best = Some(candidate);
+                    }
+                }
+            }
+            if let Some((distance, ..)) = best {
+                // Everything left lies outside the block of cells searched so far.
+                let low = self.min
+                    + Vec2::new(column as f32 - ring as f32, row as f32 - ring as f32) * self.cell;
+                let high = self.min
+                    + Vec2::new((column + ring + 1) as f32, (row + ring + 1) as f32) * self.cell;
+                let clearance = (position - low).min(high - position).min_element().max(0.0);
+                if distance <= clearance * clearance {
+                    break;
+                }
+            }
+        }
+        best.map(|(_, i, last)| (i, last))
+    }
+
+    fn remove(&mut self, index: usize) {
+        for home in self.homes[index] {
+            self.cells[home].retain(|&(i, ..)| i != index);
+        }
+    }
+}
+
+/// The cells exactly `ring` cells away from `(column, row)` in x or y, leaving out any that would
+/// fall below zero.
+fn ring_cells(column: usize, row: usize, ring: usize) -> impl Iterator<Item = (usize, usize)> {
+    let (column, row, ring) = (column as isize, row as isize, ring as isize);
+    (row - ring..=row + ring)
+        .flat_map(move |y| {
+            // Rows at the top and bottom of the ring are crossed whole, the rest only at its sides.
+            let step = if (y - row).abs() == ring { 1 } else { 2 * ring };
+            (column - ring..=column + ring)
+                .step_by(step.max(1) as usize)
+                .map(move |x| (x, y))
+        })
+        .filter(|&(x, y)| x >= 0 && y >= 0)
+        .map(|(x, y)| (x as usize, y as usize))
+}
+
+/// Reverse runs of paths, and each path within the run, while that shortens pen-up travel.
+fn two_opt(paths: &mut [Vec<Vec2>], start_at: Vec2) {
+    let start = |p: &Vec<Vec2>| p[0];
+    let end = |p: &Vec<Vec2>| p[p.len() - 1];
+    for _ in 0..MAX_TWO_OPT_PASSES {
+        let mut improved = false;
+        for i in 0..paths.len() {
+            let before_i = if i == 0 { start_at } else { end(&paths[i - 1]) };
+            for j in i + 1..paths.len() {
+                let current = before_i.distance(start(&paths[i]));
+                let candidate = before_i.distance(end(&paths[j]));
+                let (current, candidate) = match paths.get(j + 1) {
+                    Some(next) => (
+                        current + end(&paths[j]).distance(start(next)),
+                        candidate + start(&paths[i]).distance(start(next)),
+                    ),
+                    None => (current, candidate),
+                };
+                if candidate + 1e-4 < current {
+                    paths[i..=j].reverse();
+                    paths[i..=j].iter_mut().for_each(|p| p.reverse());
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+}
+
+fn join_touching(paths: Vec<Vec<Vec2>>) -> Vec<Vec<Vec2>> {
+    let mut joined: Vec<Vec<Vec2>> = Vec::with_capacity(paths.len());
+    for path in paths {
+        match joined.last_mut() {
+            Some(last) if last[last.len() - 1].distance(path[0]) <= JOIN_TOLERANCE => {
+                last.extend_from_slice(&path[1..]);
+            }
+            _ => joined.push(path),
+        }
+    }
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::optimize::{optimize, OptimizeOptions, PathStats};
+    use nannou::geom::Vec2;
+
+    fn segment(x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<Vec2> {
+        vec![Vec2::new(x0, y0), Vec2::new(x1, y1)]
+    }
+
+    #[test]
+    fn stats_measure_pen_up_from_the_start() {
+        let paths = vec![
+            segment(0.0, 10.0, 10.0, 10.0),
+            segment(10.0, 0.0, 20.0, 0.0),
+        ];
+        let stats = PathStats::of(&paths, Vec2::ZERO);
+        assert_eq!(stats.pen_down, 20.0);
+        assert_eq!(stats.pen_up, 20.0);
+    }
+
+    #[test]
+    fn reversing_and_joining_removes_pen_up_travel() {
+        // A square drawn as four sides in a scrambled order and direction.
+        let paths = vec![
+            segment(10.0, 10.0, 0.0, 10.0),
+            segment(0.0, 0.0, 10.0, 0.0),
+            segment(0.0, 0.0, 0.0, 10.0),
+            segment(10.0, 0.0, 10.0, 10.0),
+        ];
+        let (optimized, report) = optimize(paths, &OptimizeOptions::default());
+
+        assert_eq!(optimized.len(), 1);
+        assert_eq!(optimized[0].len(), 5);
+        assert_eq!(report.after.pen_up, 0.0);
+        assert_eq!(report.after.pen_down, report.before.pen_down);
+        assert!(report.before.pen_up > 0.0);
+    }
+
+    #[test]
+    fn paths_are_ordered_from_the_start() {
+        let paths = vec![
+            segment(0.0, 0.0, 10.0, 0.0),
+            segment(0.0, 100.0, 10.0, 100.0),
+        ];
+        let options = OptimizeOptions {
+            join: false,
+            start: Vec2::new(0.0, 100.0),
+            ..OptimizeOptions::default()
+        };
+        let (optimized, report) = optimize(paths, &options);
+
+        // Starting at the top, the top line is drawn first and the bottom one is drawn backwards.
+        assert_eq!(optimized[0], segment(0.0, 100.0, 10.0, 100.0));
+        assert_eq!(optimized[1], segment(10.0, 0.0, 0.0, 0.0));
+        assert_eq!(report.after.pen_up, 100.0);
+    }
+
+    #[test]
+    fn grid_search_finds_the_same_nearest_ends_as_a_full_scan() {
+        // Short segments scattered unevenly, with clusters and gaps between them.
+        let paths: Vec<Vec<Vec2>> = (0..500)
+            .map(|i| {
+                let x = ((i * 7919) % 1000) as f32 * 0.3 + if i % 3 == 0 { 400.0 } else { 0.0 };
+                let y = ((i * 104_729) % 997) as f32 * 0.2;
+                segment(x, y, x + (i % 5) as f32, y + 1.5)
+            })
+            .collect();
+        let options = OptimizeOptions {
+            join: false,
+            start: Vec2::new(-50.0, 300.0),
+            ..OptimizeOptions::default()
+        };
+        let (optimized, _) = optimize(paths.clone(), &options);
+
+        let mut remaining = paths;
+        let mut position = options.start;
+        for path in &optimized {
+            let nearest = remaining
+                .iter()
+                .flat_map(|p| [p[0], p[p.len() - 1]])
+                .map(|end| position.distance_squared(end))
+                .fold(f32::MAX, f32::min);
+            assert_eq!(position.distance_squared(path[0]), nearest);
+            let index = remaining
+                .iter()
+                .position(|p| p == path || p.iter().rev().eq(path.iter()))
+                .unwrap();
+            remaining.swap_remove(index);
+            position = path[path.len() - 1];
+        }
+        assert!(remaining.is_empty());
+    }
+
+    #[test]
+    fn two_opt_never_makes_travel_worse() {
+        let paths: Vec<Vec<Vec2>> = (0..40)
+            .map(|i| {
+                let x = ((i * 37) % 40) as f32 * 5.0;
+                let y = ((i * 11) % 7) as f32 * 9.0;
+                segment(x, y, x + 1.0, y + 2.0)
+            })
+            .collect();
+        let greedy = OptimizeOptions {
+            join: false,
+            ..OptimizeOptions::default()
+        };
+        let improved = OptimizeOptions {
+            two_opt: true,
+            ..greedy
+        };
+        let (_, greedy_report) = optimize(paths.clone(), &greedy);
+        let (_, improved_report) = optimize(paths, &improved);
+
+        assert!(greedy_report.after.pen_up < greedy_report.before.pen_up);
+        assert!(improved_report.after.pen_up <= greedy_report.after.pen_up + 1e-3);
+    }
+}
@@ -0,0 +1,520 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::args::Args;
+use crate::units::{Inch, Mm};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Paper {
+    size: PaperSize,
+    w: Mm,
+    h: Mm,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ISO216 {
+    A0,
+    A1,
+    A2,
+    A3,
+    A4,
+    A5,
+    A6,
+    A7,
+    A8,
+}
+
+impl fmt::Display for ISO216 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for ISO216 {
+    type Err = ISO216PaperLookupErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "A0" => Ok(ISO216::A0),
+            "A1" => Ok(ISO216::A1),
+            "A2" => Ok(ISO216::A2),
+            "A3" => Ok(ISO216::A3),
+            "A4" => Ok(ISO216::A4),
+            "A5" => Ok(ISO216::A5),
+            "A6" => Ok(ISO216::A6),
+            "A7" => Ok(ISO216::A7),
+            "A8" => Ok(ISO216::A8),
+            _ => Err(ISO216PaperLookupErr::UnknownSizeErr),
+        }
+    }
+}
+
+impl From<ISO216> for PaperSize {
+    fn from(size: ISO216) -> Self {
+        PaperSize::A(IsoNumber(size as u8))
+    }
+}
+
+#[derive(Debug)]
+pub enum ISO216PaperLookupErr {
+    UnknownSizeErr,
+}
+
+/// Every paper size the sketches know about. Named sizes are defined in the unit of their standard,
+/// millimetres for ISO and inches for the North American sizes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PaperSize {
+    /// ISO 216 A series, A0 to A10. See `PaperSize::a`.
+    A(IsoNumber),
+    /// ISO 216 B series, B0 to B10. See `PaperSize::b`.
+    B(IsoNumber),
+    /// ISO 269 C series, C0 to C10, the envelopes that fit the A series. See `PaperSize::c`.
+    C(IsoNumber),
+    /// 8.5 x 11 in, also ANSI A.
+    Letter,
+    /// 8.5 x 14 in.
+    Legal,
+    /// 11 x 17 in, also ANSI B or Ledger.
+    Tabloid,
+    /// 17 x 22 in.
+    AnsiC,
+    /// 22 x 34 in.
+    AnsiD,
+    /// 34 x 44 in.
+    AnsiE,
+    /// 9 x 12 in.
+    ArchA,
+    /// 12 x 18 in.
+    ArchB,
+    /// 18 x 24 in.
+    ArchC,
+    /// 24 x 36 in.
+    ArchD,
+    /// 36 x 48 in.
+    ArchE,
+    /// 30 x 42 in.
+    ArchE1,
+    /// Photo prints, 4 x 6 in.
+    Photo4x6,
+    /// Photo prints, 5 x 7 in.
+    Photo5x7,
+    /// Photo prints, 8 x 10 in.
+    Photo8x10,
+    /// Photo prints, 11 x 14 in.
+    Photo11x14,
+    /// Photo prints, 13 x 19 in, also Super B.
+    Photo13x19,
+    /// Any other size.
+    Custom { w: Mm, h: Mm },
+}
+
+/// The largest number in each of the ISO series.
+const ISO_SERIES_MAX: u8 = 10;
+
+/// The number of a size in an ISO series, from 0 to 10. Only `IsoNumber::new` makes one, so every
+/// ISO `PaperSize` has real dimensions.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct IsoNumber(u8);
+
+impl IsoNumber {
+    pub fn new(n: u8) -> Result<Self, PaperParseErr> {
+        if n <= ISO_SERIES_MAX {
+            Ok(IsoNumber(n))
+        } else {
+            Err(PaperParseErr::IsoNumberErr(n))
+        }
+    }
+
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl PaperSize {
+    /// A size in the ISO A series, such as `PaperSize::a(4)` for A4.
+    pub fn a(n: u8) -> Result<Self, PaperParseErr> {
+        IsoNumber::new(n).map(PaperSize::A)
+    }
+
+    /// A size in the ISO B series.
+    pub fn b(n: u8) -> Result<Self, PaperParseErr> {
+        IsoNumber::new(n).map(PaperSize::B)
+    }
+
+    /// A size in the ISO C series.
+    pub fn c(n: u8) -> Result<Self, PaperParseErr> {
+        IsoNumber::new(n).map(PaperSize::C)
+    }
+
+    /// Width and height, short side first for named sizes.
+    pub fn dimensions(&self) -> (Mm, Mm) {
+        let inches = |w: f64, h: f64| (Inch(w).into(), Inch(h).into());
+        match *self {
+            PaperSize::A(n) => iso_series(841, 1189, n.get()),
+            PaperSize::B(n) => iso_series(1000, 1414, n.get()),
+            PaperSize::C(n) => iso_series(917, 1297, n.get()),
+            PaperSize::Letter => inches(8.5, 11.0),
+            PaperSize::Legal => inches(8.5, 14.0),
+            PaperSize::Tabloid => inches(11.0, 17.0),
+            PaperSize::AnsiC => inches(17.0, 22.0),
+            PaperSize::AnsiD => inches(22.0, 34.0),
+            PaperSize::AnsiE => inches(34.0, 44.0),
+            PaperSize::ArchA => inches(9.0, 12.0),
+            PaperSize::ArchB => inches(12.0, 18.0),
+            PaperSize::ArchC => inches(18.0, 24.0),
+            PaperSize::ArchD => inches(24.0, 36.0),
+            PaperSize::ArchE => inches(36.0, 48.0),
+            PaperSize::ArchE1 => inches(30.0, 42.0),
+            PaperSize::Photo4x6 => inches(4.0, 6.0),
+            PaperSize::Photo5x7 => inches(5.0, 7.0),
+            PaperSize::Photo8x10 => inches(8.0, 10.0),
+            PaperSize::Photo11x14 => inches(11.0, 14.0),
+            PaperSize::Photo13x19 => inches(13.0, 19.0),
+            PaperSize::Custom { w, h } => (w, h),
+        }
+    }
+}
+
+/// Each size in an ISO series halves the longer side of the one before it, rounding down.
+fn iso_series(shortest: i32, longest: i32, n: u8) -> (Mm, Mm) {
+    let (mut shortest, mut longest) = (shortest, longest);
+    (0..n).for_each(|_| {
+        let temp = longest;
+        longest = shortest;
+        shortest = temp / 2;
+    });
+    (Mm(shortest as f64), Mm(longest as f64))
+}
+
+impl fmt::Display for PaperSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaperSize::A(n) => write!(f, "A{}", n.get()),
+            PaperSize::B(n) => write!(f, "B{}", n.get()),
+            PaperSize::C(n) => write!(f, "C{}", n.get()),
+            PaperSize::Letter => write!(f, "Letter"),
+            PaperSize::Legal => write!(f, "Legal"),
+            PaperSize::Tabloid => write!(f, "Tabloid"),
+            PaperSize::AnsiC => write!(f, "ANSI-C"),
+            PaperSize::AnsiD => write!(f, "ANSI-D"),
+            PaperSize::AnsiE => write!(f, "ANSI-E"),
+            PaperSize::ArchA => write!(f, "Arch-A"),
+            PaperSize::ArchB => write!(f, "Arch-B"),
+            PaperSize::ArchC => write!(f, "Arch-C"),
+            PaperSize::ArchD => write!(f, "Arch-D"),
+            PaperSize::ArchE => write!(f, "Arch-E"),
+            PaperSize::ArchE1 => write!(f, "Arch-E1"),
+            PaperSize::Photo4x6 => write!(f, "Photo-4x6"),
+            PaperSize::Photo5x7 => write!(f, "Photo-5x7"),
+            PaperSize::Photo8x10 => write!(f, "Photo-8x10"),
+            PaperSize::Photo11x14 => write!(f, "Photo-11x14"),
+            PaperSize::Photo13x19 => write!(f, "Photo-13x19"),
+            PaperSize::Custom { w, h } => write!(f, "{}x{}mm", w.0, h.0),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PaperParseErr {
+    /// Not a known paper name or a `WxH` size with a unit.
+    UnknownSizeErr(String),
+    /// A custom size whose width or height is not a positive number.
+    BadDimensionsErr(String),
+    /// A custom size in a unit other than `mm`, `cm` or `in`.
+    UnknownUnitErr(String),
+    /// A number past the end of the ISO series.
+    IsoNumberErr(u8),
+}
+
+impl fmt::Display for PaperParseErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaperParseErr::UnknownSizeErr(s) => write!(f, "unknown paper size {:?}", s),
+            PaperParseErr::BadDimensionsErr(s) => write!(f, "bad paper dimensions {:?}", s),
+            PaperParseErr::UnknownUnitErr(s) => {
+                write!(f, "unknown unit in {:?}, expected mm, cm or in", s)
+            }
+            PaperParseErr::IsoNumberErr(n) => write!(
+                f,
+                "ISO paper sizes run from 0 to {}, not {}",
+                ISO_SERIES_MAX, n
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PaperParseErr {}
+
+impl FromStr for PaperSize {
+    type Err = PaperParseErr;
+
+    /// Parse a paper name such as `A3`, `letter`, `ANSI-D`, `arch-e1` or `photo-5x7`, or a custom
+    /// size such as `11x17in`, `300x400mm` or `50x70cm`. Names ignore case, spaces and hyphens.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name: String = s
+            .chars()
+            .filter(|c| !matches!(c, '-' | ' ' | '_'))
+            .collect::<String>()
+            .to_lowercase();
+        let named = match name.as_str() {
+            "letter" | "ansia" => Some(PaperSize::Letter),
+            "legal" => Some(PaperSize::Legal),
+            "tabloid" | "ledger" | "ansib" => Some(PaperSize::Tabloid),
+            "ansic" => Some(PaperSize::AnsiC),
+            "ansid" => Some(PaperSize::AnsiD),
+            "ansie" => Some(PaperSize::AnsiE),
+            "archa" => Some(PaperSize::ArchA),
+            "archb" => Some(PaperSize::ArchB),
+            "archc" => Some(PaperSize::ArchC),
+            "archd" => Some(PaperSize::ArchD),
+            "arche" => Some(PaperSize::ArchE),
+            "arche1" => Some(PaperSize::ArchE1),
+            "photo4x6" => Some(PaperSize::Photo4x6),
+            "photo5x7" => Some(PaperSize::Photo5x7),
+            "photo8x10" => Some(PaperSize::Photo8x10),
+            "photo11x14" => Some(PaperSize::Photo11x14),
+            "photo13x19" | "superb" => Some(PaperSize::Photo13x19),
+            _ => None,
+        };
+        if let Some(size) = named {
+            return Ok(size);
+        }
+
+        let mut chars = name.chars();
+        let series = chars.next();
+        if let (Some(series @ ('a' | 'b' | 'c')), Ok(n)) = (series, chars.as_str().parse::<u8>()) {
+            if let Ok(n) = IsoNumber::new(n) {
+                return Ok(match series {
+                    'a' => PaperSize::A(n),
+                    'b' => PaperSize::B(n),
+                    _ => PaperSize::C(n),
+                });
+            }
+        }
+
+        parse_custom(&name).unwrap_or_else(|| Err(PaperParseErr::UnknownSizeErr(s.to_string())))
+    }
+}
+
+/// Parse `WxH` followed by a unit. Returns `None` if the string does not look like a size at all.
+fn parse_custom(name: &str) -> Option<Result<PaperSize, PaperParseErr>> {
+    let (w, rest) = name.split_once('x')?;
+    let unit_start = rest.find(|c: char| c.is_ascii_alphabetic())?;
+    let (h, unit) = rest.split_at(unit_start);
+    let to_mm: fn(f64) -> Mm = match unit {
+        "mm" => Mm,
+        "cm" => |v| Mm(v * 10.0),
+        "in" => |v| Inch(v).into(),
+        _ => return Some(Err(PaperParseErr::UnknownUnitErr(name.to_string()))),
+    };
+    let bad = || PaperParseErr::BadDimensionsErr(name.to_string());
+    let parse = |s: &str| match s.parse::<f64>() {
+        Ok(v) if v > 0.0 && v.is_finite() => Ok(to_mm(v)),
+        _ => Err(bad()),
+    };
+    Some(parse(w).and_then(|w| Ok(PaperSize::Custom { w, h: parse(h)? })))
+}
+
+impl Paper {
+    /// A sheet of `size` turned to `orientation`.
+    pub fn new(size: PaperSize, orientation: Orientation) -> Self {
+        let (w, h) = size.dimensions();
+        let (shortest, longest) = (w.min(h), w.max(h));
+        let (w, h) = match orientation {
+            Orientation::Portrait => (shortest, longest),
+            Orientation::Landscape => (longest, shortest),
+        };
+        Paper { size, w, h }
+    }
+
+    pub fn from_iso216(size: ISO216, orientation: Orientation) -> Self {
+        Paper::new(size.into(), orientation)
+    }
+
+    /// The paper chosen with `--paper A3` and `--landscape`, defaulting to A4 portrait. Any size
+    /// that parses as a `Paper` is accepted, such as `letter`, `A3-landscape` or `11x17in`.
+    pub fn from_args(args: &Args) -> Self {
+        let paper = args
+            .parsed::<Paper>("paper")
+            .unwrap_or_else(|| Paper::from_iso216(ISO216::A4, Orientation::Portrait));
+        if args.flag("landscape") {
+            paper.oriented(Orientation::Landscape)
+        } else {
+            paper
+        }
+    }
+
+    /// The same sheet turned to `orientation`.
+    pub fn oriented(&self, orientation: Orientation) -> Self {
+        Paper::new(self.size, orientation)
+    }
+
+    pub fn size(&self) -> PaperSize {
+        self.size
+    }
+
+    pub fn width(&self) -> Mm {
+        self.w
+    }
+
+    pub fn height(&self) -> Mm {
+        self.h
+    }
+
+    /// The paper's size in whole pixels at `dpi`, rounded to the nearest pixel.
+    pub fn pixel_dimensions(&self, dpi: u16) -> (u32, u32) {
+        let dpi = dpi as f64;
+        (
+            self.w.to_px(dpi).round() as u32,
+            self.h.to_px(dpi).round() as u32,
+        )
+    }
+}
+
+impl FromStr for Paper {
+    type Err = PaperParseErr;
+
+    /// Parse a paper size with an optional orientation, such as `A3-landscape` or `letter`. Named
+    /// sizes default to portrait. Custom sizes keep the width and height as written unless an
+    /// orientation is given, so `17x11in` is already landscape.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        for (suffix, orientation) in [
+            ("landscape", Orientation::Landscape),
+            ("portrait", Orientation::Portrait),
+        ] {
+            if let Some(size) = lower.strip_suffix(suffix) {
+                let size = size.trim_end_matches(['-', ' ', '_']);
+                return Ok(Paper::new(size.parse()?, orientation));
+            }
+        }
+        match s.parse()? {
+            PaperSize::Custom { w, h } => {
+                let orientation = if w > h {
+                    Orientation::Landscape
+                } else {
+                    Orientation::Portrait
+                };
+                Ok(Paper::new(PaperSize::Custom { w, h }, orientation))
+            }
+            size => Ok(Paper::new(size, Orientation::Portrait)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::paper::*;
+
+    #[test]
+    fn test_from_iso216() {
+        let portrait = Paper::from_iso216(ISO216::A0, Orientation::Portrait);
+        assert_eq!(portrait.w, Mm(841.0), "{}", portrait.w.0);
+        assert_eq!(portrait.h, Mm(1189.0), "{}", portrait.h.0);
+        let portrait = Paper::from_iso216(ISO216::A1, Orientation::Portrait);
+        assert_eq!(portrait.w, Mm(594.0), "{}", portrait.w.0);
+        assert_eq!(portrait.h, Mm(841.0), "{}", portrait.h.0);
+        let portrait = Paper::from_iso216(ISO216::A2, Orientation::Portrait);
+        assert_eq!(portrait.w, Mm(420.0), "{}", portrait.w.0);
+        assert_eq!(portrait.h, Mm(594.0), "{}", portrait.h.0);
+        let portrait = Paper::from_iso216(ISO216::A3, Orientation::Portrait);
+        assert_eq!(portrait.w, Mm(297.0), "{}", portrait.w.0);
+        assert_eq!(portrait.h, Mm(420.0), "{}", portrait.h.0);
+        let portrait = Paper::from_iso216(ISO216::A4, Orientation::Portrait);
+        assert_eq!(portrait.w, Mm(210.0), "{}", portrait.w.0);
+        assert_eq!(portrait.h, Mm(297.0), "{}", portrait.h.0);
+
+        let landscape = Paper::from_iso216(ISO216::A0, Orientation::Landscape);
+        assert_eq!(landscape.h, Mm(841.0), "{}", portrait.h.0);
+        assert_eq!(landscape.w, Mm(1189.0), "{}", portrait.w.0);
+    }
+
+    #[test]
+    fn test_iso_series() {
+        let dimensions = |size: Result<PaperSize, PaperParseErr>| size.unwrap().dimensions();
+        assert_eq!(dimensions(PaperSize::a(10)), (Mm(26.0), Mm(37.0)));
+        assert_eq!(dimensions(PaperSize::b(0)), (Mm(1000.0), Mm(1414.0)));
+        assert_eq!(dimensions(PaperSize::b(5)), (Mm(176.0), Mm(250.0)));
+        assert_eq!(dimensions(PaperSize::c(4)), (Mm(229.0), Mm(324.0)));
+        assert_eq!(dimensions(PaperSize::c(6)), (Mm(114.0), Mm(162.0)));
+    }
+
+    #[test]
+    fn iso_numbers_stop_at_ten() {
+        assert_eq!(PaperSize::a(200), Err(PaperParseErr::IsoNumberErr(200)));
+        assert_eq!(
+            PaperSize::c(11).unwrap_err().to_string(),
+            "ISO paper sizes run from 0 to 10, not 11"
+        );
+    }
+
+    #[test]
+    fn test_parse_sizes() {
+        assert_eq!("a3".parse(), PaperSize::a(3));
+        assert_eq!("B10".parse(), PaperSize::b(10));
+        assert_eq!("Letter".parse(), Ok(PaperSize::Letter));
+        assert_eq!("ANSI-B".parse(), Ok(PaperSize::Tabloid));
+        assert_eq!("arch e1".parse(), Ok(PaperSize::ArchE1));
+        assert_eq!("photo-5x7".parse(), Ok(PaperSize::Photo5x7));
+        assert_eq!(
+            "50x70cm".parse(),
+            Ok(PaperSize::Custom {
+                w: Mm(500.0),
+                h: Mm(700.0)
+            })
+        );
+        assert_eq!(
+            "A11".parse::<PaperSize>(),
+            Err(PaperParseErr::UnknownSizeErr("A11".to_string()))
+        );
+        assert_eq!(
+            "11x17ft".parse::<PaperSize>(),
+            Err(PaperParseErr::UnknownUnitErr("11x17ft".to_string()))
+        );
+        assert_eq!(
+            "0x17in".parse::<PaperSize>(),
+            Err(PaperParseErr::BadDimensionsErr("0x17in".to_string()))
+        );
+        // Every named size parses back from its own name.
+        for size in [
+            PaperSize::c(7).unwrap(),
+            PaperSize::AnsiD,
+            PaperSize::ArchA,
+            PaperSize::Photo13x19,
+        ] {
+            assert_eq!(size.to_string().parse(), Ok(size));
+        }
+    }
+
+    #[test]
+    fn test_parse_paper() {
+        let paper: Paper = "A3-landscape".parse().unwrap();
+        assert_eq!((paper.width(), paper.height()), (Mm(420.0), Mm(297.0)));
+        assert_eq!(Ok(paper.size()), PaperSize::a(3));
+
+        let close = |a: Mm, b: f64| (a.0 - b).abs() < 1e-9;
+        let paper: Paper = "11x17in".parse().unwrap();
+        assert!(close(paper.width(), 279.4) && close(paper.height(), 431.8));
+
+        let paper: Paper = "17x11in".parse().unwrap();
+        assert!(close(paper.width(), 431.8) && close(paper.height(), 279.4));
+        let paper: Paper = "17x11in portrait".parse().unwrap();
+        assert!(close(paper.width(), 279.4) && close(paper.height(), 431.8));
+    }
+
+    #[test]
+    fn test_dpi() {
+        let paper = Paper::from_iso216(ISO216::A4, Orientation::Portrait);
+        let (w, h) = paper.pixel_dimensions(300);
+        assert_eq!(2480, w);
+        assert_eq!(3508, h);
+
+        let paper: Paper = "letter".parse().unwrap();
+        assert_eq!(paper.pixel_dimensions(600), (5100, 6600));
+    }
+}
@@ -0,0 +1,571 @@
+//! Vector export of a `Scene` as a single page PDF at the true size of the paper.
+//!
+//! The file is written by hand: one page whose content stream draws the scene with paths and
+//! Bézier circles, uncompressed so that it stays readable. Colours are device RGB by default or
+//! device CMYK with `PdfOptions::cmyk`, and palette entries can be named as spot colours so that a
+//! print shop can separate them onto their own plates. The sketch name, seed and command line are
+//! kept in the document information dictionary.
+
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use nannou::color::{Srgb, Srgba};
+use nannou::geom::Vec2;
+
+use crate::scene::{Scene, Shape, Stroke};
+use crate::svg::hex;
+use crate::units::{Mm, Pt};
+
+/// How far the control points of a Bézier quarter circle sit from its ends, per unit of radius.
+const KAPPA: f32 = 0.552_284_8;
+/// The separation name PDF reserves for registration: a spot colour by this name is printed on
+/// every plate. Strokes flagged as `Stroke::registration` are written in it.
+pub const ALL_SEPARATIONS: &str = "All";
+
+/// A palette colour printed with its own named ink.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpotColor {
+    pub color: Srgb<u8>,
+    pub name: String,
+}
+
+/// A list of spot colours, parsed from `#45868f=PANTONE 5473 C,#fa7a7a=Warm Red`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpotColors(pub Vec<SpotColor>);
+
+#[derive(Debug, PartialEq)]
+pub enum SpotColorParseErr {
+    /// An entry without a `=` between the colour and its name, or with an empty name.
+    MissingNameErr(String),
+    /// A colour that is not six hex digits.
+    BadHexErr(String),
+}
+
+impl fmt::Display for SpotColorParseErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpotColorParseErr::MissingNameErr(s) => {
+                write!(f, "spot colour {:?} has no name, expected #rrggbb=Name", s)
+            }
+            SpotColorParseErr::BadHexErr(s) => write!(f, "bad spot colour hex {:?}", s),
+        }
+    }
+}
+
+impl std::error::Error for SpotColorParseErr {}
+
+impl FromStr for SpotColors {
+    type Err = SpotColorParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let (hex, name) = entry
+                    .split_once('=')
+                    .filter(|(_, name)| !name.trim().is_empty())
+                    .ok_or_else(|| SpotColorParseErr::MissingNameErr(entry.to_string()))?;
+                let hex = hex.trim().trim_start_matches('#');
+                let channel = |i: usize| {
+                    hex.get(i..i + 2)
+                        .and_then(|c| u8::from_str_radix(c, 16).ok())
+                        .ok_or_else(|| SpotColorParseErr::BadHexErr(hex.to_string()))
+                };
+                if hex.len() != 6 {
+                    return Err(SpotColorParseErr::BadHexErr(hex.to_string()));
+                }
+                Ok(SpotColor {
+                    color: Srgb::new(channel(0)?, channel(2)?, channel(4)?),
+                    name: name.trim().to_string(),
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(SpotColors)
+    }
+}
+
+/// Where the document came from, kept in its information dictionary.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PdfMetadata {
+    pub sketch: String,
+    /// None when the sketch rendered a loaded model rather than generating one.
+    pub seed: Option<u64>,
+    /// The options the sketch was run with.
+    pub parameters: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PdfOptions {
+    /// Write process colours as device CMYK rather than device RGB.
+    pub cmyk: bool,
+    pub spot_colors: SpotColors,
+    pub metadata: PdfMetadata,
+}
+
+/// The naive conversion from RGB to CMYK, with black taken out as the darkest channel.
+pub fn cmyk(color: Srgb<u8>) -> [f32; 4] {
+    let (r, g, b) = (
+        color.red as f32 / 255.0,
+        color.green as f32 / 255.0,
+        color.blue as f32 / 255.0,
+    );
+    let k = 1.0 - r.max(g).max(b);
+    if k >= 1.0 {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+    [
+        (1.0 - r - k) / (1.0 - k),
+        (1.0 - g - k) / (1.0 - k),
+        (1.0 - b - k) / (1.0 - k),
+        k,
+    ]
+}
+
+/// Render the scene onto a page `width` by `height`. The scene is scaled to fit the page, keeping
+/// its aspect ratio and centring it.
+pub fn render(scene: &Scene, width: Mm, height: Mm, options: &PdfOptions) -> Vec<u8> {
+    let (page_w, page_h) = (Pt::from(width).0 as f32, Pt::from(height).0 as f32);
+    let mut page = Page::new(options);
+    let scale = (page_w / scene.rect.w()).min(page_h / scene.rect.h());
+    let offset = Vec2::new(page_w, page_h) * 0.5 - scene.rect.xy() * scale;
+    writeln!(
+        page.content,
+        "{} 0 0 {} {} {} cm",
+        num(scale),
+        num(scale),
+        num(offset.x),
+        num(offset.y)
+    )
+    .unwrap();
+    page.content.push_str("1 j 1 J\n");
+    page.fill_color(scene.background);
+    page.path(&crate::scene::rect_points(&scene.rect), true);
+    page.content.push_str("f\n");
+    for shape in &scene.shapes {
+        page.shape(shape);
+    }
+    page.finish(page_w, page_h)
+}
+
+pub fn save(
+    scene: &Scene,
+    width: Mm,
+    height: Mm,
+    options: &PdfOptions,
+    path: &Path,
+) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, render(scene, width, height, options))
+}
+
+/// The content stream for the page along with the resources it refers to.
+struct Page<'a> {
+    options: &'a PdfOptions,
+    content: String,
+    /// Spot colour spaces used so far, by hex or `ALL_SEPARATIONS` for registration, in the order
+    /// of their resource names.
+    separations: Vec<(String, SpotColor)>,
+    /// Graphics states for each opacity used so far, by alpha.
+    opacities: HashMap<u8, String>,
+    /// The opacity currently in effect.
+    alpha: Option<u8>,
+}
+
+impl<'a> Page<'a> {
+    fn new(options: &'a PdfOptions) -> Self {
+        Page {
+            options,
+            content: String::new(),
+            separations: Vec::new(),
+            opacities: HashMap::new(),
+            alpha: None,
+        }
+    }
+
+    fn shape(&mut self, shape: &Shape) {
+        match shape {
+            Shape::Polyline { points, stroke } => {
+                self.stroke_style(stroke);
+                self.path(points, false);
+                self.content.push_str("S\n");
+            }
+            Shape::Polygon {
+                points,
+                fill,
+                stroke,
+            } => {
+                if let Some(fill) = fill {
+                    self.fill_color(*fill);
+                    self.path(points, true);
+                    self.content.push_str("f\n");
+                }
+                if let Some(stroke) = stroke {
+                    self.stroke_style(stroke);
+                    self.path(points, true);
+                    self.content.push_str("S\n");
+                }
+            }
+            Shape::Ellipse { xy, wh, fill } => {
+                self.fill_color(*fill);
+                self.ellipse(*xy, *wh * 0.5);
+                self.content.push_str("f\n");
+            }
+        }
+    }
+
+    fn path(&mut self, points: &[Vec2], close: bool) {
+        for (i, p) in points.iter().enumerate() {
+            let op = if i == 0 { "m" } else { "l" };
+            writeln!(self.content, "{} {} {}", num(p.x), num(p.y), op).unwrap();
+        }
+        if close {
+            self.content.push_str("h\n");
+        }
+    }
+
+    /// An ellipse as four Bézier quarters, counter-clockwise from the right.
+    fn ellipse(&mut self, centre: Vec2, radii: Vec2) {
+        let (rx, ry) = (radii.x, radii.y);
+        let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+        let c = centre;
+        writeln!(self.content, "{} {} m", num(c.x + rx), num(c.y)).unwrap();
+        let quarters = [
+            [(rx, ky), (kx, ry), (0.0, ry)],
+            [(-kx, ry), (-rx, ky), (-rx, 0.0)],
+            [(-rx, -ky), (-kx, -ry), (0.0, -ry)],
+            [(kx, -ry), (rx, -ky), (rx, 0.0)],
+        ];
+        for quarter in quarters {
+            for (x, y) in quarter {
+                write!(self.content, "{} {} ", num(c.x + x), num(c.y + y)).unwrap();
+            }
+            self.content.push_str("c\n");
+        }
+        self.content.push_str("h\n");
+    }
+
+    fn stroke_style(&mut self, stroke: &Stroke) {
+        if stroke.registration {
+            self.registration_color(stroke.color.alpha);
+        } else {
+            self.color(stroke.color, true);
+        }
+        writeln!(self.content, "{} w", num(stroke.weight)).unwrap();
+    }
+
+    fn fill_color(&mut self, color: Srgba<u8>) {
+        self.color(color, false);
+    }
+
+    /// Stroke in the registration colour, at full strength on every plate.
+    fn registration_color(&mut self, alpha: u8) {
+        self.alpha(alpha);
+        let spot = SpotColor {
+            color: Srgb::new(0, 0, 0),
+            name: ALL_SEPARATIONS.to_string(),
+        };
+        let name = self.separation_name(ALL_SEPARATIONS.to_string(), spot);
+        writeln!(self.content, "/{} CS 1 SCN", name).unwrap();
+    }
+
+    fn alpha(&mut self, alpha: u8) {
+        if self.alpha != Some(alpha) {
+            let opacity = self.opacity(alpha);
+            writeln!(self.content, "/{} gs", opacity).unwrap();
+            self.alpha = Some(alpha);
+        }
+    }
+
+    fn color(&mut self, color: Srgba<u8>, stroke: bool) {
+        self.alpha(color.alpha);
+        let rgb = Srgb::new(color.red, color.green, color.blue);
+        if let Some(name) = self.separation(color) {
+            let (space, tint) = if stroke { ("CS", "SCN") } else { ("cs", "scn") };
+            writeln!(self.content, "/{} {} 1 {}", name, space, tint).unwrap();
+        } else if self.options.cmyk {
+            let [c, m, y, k] = cmyk(rgb);
+            let op = if stroke { "K" } else { "k" };
+            writeln!(
+                self.content,
+                "{} {} {} {} {}",
+                num(c),
+                num(m),
+                num(y),
+                num(k),
+                op
+            )
+            .unwrap();
+        } else {
+            let op = if stroke { "RG" } else { "rg" };
+            writeln!(
+                self.content,
+                "{} {} {} {}",
+                num(rgb.red as f32 / 255.0),
+                num(rgb.green as f32 / 255.0),
+                num(rgb.blue as f32 / 255.0),
+                op
+            )
+            .unwrap();
+        }
+    }
+
+    /// The resource name of the spot colour for `color`, if it is one.
+    fn separation(&mut self, color: Srgba<u8>) -> Option<String> {
+        let key = hex(color);
+        let spot = self
+            .options
+            .spot_colors
+            .0
+            .iter()
+            .find(|spot| hex(spot.color.into()) == key)?
+            .clone();
+        Some(self.separation_name(key, spot))
+    }
+
+    /// The resource name of the colour space for `spot`, added the first time `key` is used.
+    fn separation_name(&mut self, key: String, spot: SpotColor) -> String {
+        let index = match self.separations.iter().position(|(k, _)| *k == key) {
+            Some(index) => index,
+            None => {
+                self.separations.push((key, spot));
+                self.separations.len() - 1
+            }
+        };
+        format!("CS{}", index)
+    }
+
+    fn opacity(&mut self, alpha: u8) -> String {
+        let count = self.opacities.len();
+        self.opacities
+            .entry(alpha)
+            .or_insert_with(|| format!("GS{}", count))
+            .clone()
+    }
+
+    /// Assemble the document around the content stream.
+    fn finish(self, page_w: f32, page_h: f32) -> Vec<u8> {
+        let mut objects: Vec<String> = Vec::new();
+        // Catalog, pages, page, contents and information come first, separations after them.
+        objects.push("<< /Type /Catalog /Pages 2 0 R >>".to_string());
+        objects.push("<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string());
+
+        let mut color_spaces = String::new();
+        for (i, _) in self.separations.iter().enumerate() {
+            write!(color_spaces, " /CS{} {} 0 R", i, 6 + i).unwrap();
+        }
+        let mut opacities: Vec<(&u8, &String)> = self.opacities.iter().collect();
+        opacities.sort_by_key(|(_, name)| (*name).clone());
+        let mut states = String::new();
+        for (alpha, name) in opacities {
+            let alpha = num(*alpha as f32 / 255.0);
+            write!(states, " /{} << /ca {} /CA {} >>", name, alpha, alpha).unwrap();
+        }
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /ColorSpace <<{} >> /ExtGState <<{} >> >> /Contents 4 0 R >>",
+            num(page_w),
+            num(page_h),
+            color_spaces,
+            states
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            self.content.len(),
+            self.content
+        ));
+        let metadata = &self.options.metadata;
+        let (subject, seed) = match metadata.seed {
+            Some(seed) => (
+                format!("{} with seed {}", metadata.sketch, seed),
+                format!(" /Seed {}", string(&seed.to_string())),
+            ),
+            None => (metadata.sketch.clone(), String::new()),
+        };
+        objects.push(format!(
+            "<< /Title {} /Subject {} /Producer (canvas) /Sketch {}{} /Parameters {} >>",
+            string(&metadata.sketch),
+            string(&subject),
+            string(&metadata.sketch),
+            seed,
+            string(&metadata.parameters)
+        ));
+        for (_, spot) in &self.separations {
+            let [c, m, y, k] = if spot.name == ALL_SEPARATIONS {
+                [1.0; 4]
+            } else {
+                cmyk(spot.color)
+            };
+            objects.push(format!(
+                "[/Separation /{} /DeviceCMYK << /FunctionType 2 /Domain [0 1] /C0 [0 0 0 0] /C1 [{} {} {} {}] /N 1 >>]",
+                name(&spot.name),
+                num(c),
+                num(m),
+                num(y),
+                num(k)
+            ));
+        }
+
+        let mut pdf = String::from("%PDF-1.4\n");
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            write!(pdf, "{} 0 obj\n{}\nendobj\n", i + 1, object).unwrap();
+        }
+        let xref = pdf.len();
+        write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).unwrap();
+        for offset in offsets {
+            writeln!(pdf, "{:010} 00000 n ", offset).unwrap();
+        }
+        write!(
+            pdf,
+            "trailer\n<< /Size {} /Root 1 0 R /Info 5 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        )
+        .unwrap();
+        pdf.into_bytes()
+    }
+}
+
+/// A number with at most three decimals and no trailing zeros.
+fn num(value: f32) -> String {
+    let formatted = format!("{:.3}", value);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "-0" | "" => "0".to_string(),
+        _ => trimmed.to_string(),
+    }
+}
+
+/// A PDF literal string. Anything outside printable ASCII is replaced, as the information
+/// dictionary is read as PDFDocEncoding.
+fn string(value: &str) -> String {
+    let mut escaped = String::from("(");
+    for c in value.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped.push(')');
+    escaped
+}
+
+/// A PDF name, without its leading slash, with anything but regular characters written as `#xx`.
+fn name(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'!'..=b'~' if !b"#()<>[]{}/%".contains(&b) => (b as char).to_string(),
+            _ => format!("#{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pdf::{cmyk, render, PdfMetadata, PdfOptions, SpotColors};
+    use crate::scene::Scene;
+    use crate::units::Mm;
+    use nannou::color::{Srgb, Srgba};
+    use nannou::geom::{Rect, Vec2};
+
+    fn scene() -> Scene {
+        let mut scene = Scene::new(Rect::from_w_h(210.0, 297.0), Srgb::new(255u8, 255, 255));
+        scene.rect(
+            &Rect::from_x_y_w_h(0.0, 0.0, 20.0, 20.0),
+            Srgb::new(0x45u8, 0x86, 0x8f),
+        );
+        scene.polyline(
+            vec![Vec2::new(-50.0, 0.0), Vec2::new(50.0, 10.0)],
+            Srgba::new(0u8, 0, 0, 128),
+            0.5,
+        );
+        scene.ellipse(Vec2::new(10.0, 10.0), 1.0, Srgb::new(0xfau8, 0x7a, 0x7a));
+        scene
+    }
+
+    fn options() -> PdfOptions {
+        PdfOptions {
+            metadata: PdfMetadata {
+                sketch: "cells".to_string(),
+                seed: Some(42),
+                parameters: "--paper A4 (draft)".to_string(),
+            },
+            ..PdfOptions::default()
+        }
+    }
+
+    #[test]
+    fn loaded_models_have_no_seed() {
+        let mut options = options();
+        options.metadata.seed = None;
+        let pdf = String::from_utf8(render(&scene(), Mm(210.0), Mm(297.0), &options)).unwrap();
+        assert!(
+            pdf.contains("/Subject (cells) /Producer (canvas) /Sketch (cells) /Parameters"),
+            "{}",
+            pdf
+        );
+    }
+
+    #[test]
+    fn page_is_the_paper_size_with_a_valid_xref() {
+        let pdf = String::from_utf8(render(&scene(), Mm(210.0), Mm(297.0), &options())).unwrap();
+
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.contains("/MediaBox [0 0 595.276 841.89]"), "{}", pdf);
+        // Millimetres in the scene map onto points.
+        assert!(
+            pdf.contains("2.835 0 0 2.835 297.638 420.945 cm"),
+            "{}",
+            pdf
+        );
+        assert!(pdf.contains("/Sketch (cells) /Seed (42) /Parameters (--paper A4 \\(draft\\))"));
+        assert!(pdf.contains("/GS1 << /ca 0.502 /CA 0.502 >>"), "{}", pdf);
+        assert_eq!(pdf.matches(" c\n").count(), 4);
+
+        // Every xref entry points at the start of its object.
+        let xref = pdf.rfind("\nxref\n").unwrap() + 1;
+        let startxref: usize = pdf.lines().rev().nth(1).unwrap().parse().unwrap();
+        assert_eq!(startxref, xref);
+        for (i, line) in pdf[xref..].lines().skip(3).take(5).enumerate() {
+            let offset: usize = line[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj", i + 1)));
+        }
+    }
+
+    #[test]
+    fn palette_entries_can_be_spot_colours() {
+        let options = PdfOptions {
+            cmyk: true,
+            spot_colors: "#45868f=PANTONE 5473 C".parse().unwrap(),
+            ..options()
+        };
+        let pdf = String::from_utf8(render(&scene(), Mm(210.0), Mm(297.0), &options)).unwrap();
+
+        assert!(pdf.contains("/ColorSpace << /CS0 6 0 R >>"), "{}", pdf);
+        assert!(pdf.contains("/CS0 cs 1 scn"), "{}", pdf);
+        assert!(pdf.contains("[/Separation /PANTONE#205473#20C /DeviceCMYK"));
+        // The other colours are process CMYK.
+        assert!(pdf.contains("0 0.512 0.512 0.02 k"), "{}", pdf);
+        assert!(pdf.contains("0 0 0 1 K"), "{}", pdf);
+    }
+
+    #[test]
+    fn parse_spot_colors() {
+        let spots: SpotColors = "#45868f=PANTONE 5473 C, fa7a7a = Warm Red".parse().unwrap();
+        assert_eq!(spots.0.len(), 2);
+        assert_eq!(spots.0[1].color, Srgb::new(0xfa, 0x7a, 0x7a));
+        assert_eq!(spots.0[1].name, "Warm Red");
+        assert!("#45868f".parse::<SpotColors>().is_err());
+        assert!("#4586=Teal".parse::<SpotColors>().is_err());
+        assert_eq!(cmyk(Srgb::new(0, 0, 0)), [0.0, 0.0, 0.0, 1.0]);
+    }
+}
@@ -0,0 +1,415 @@
+//! Turns a `Scene` into pen paths measured in millimetres on a sheet of paper, ready for the plotter
+//! formats in `gcode` and `hpgl`.
+//!
+//! Plot coordinates put the origin at the bottom left corner of the paper with y pointing up.
+
+use std::fmt;
+
+use nannou::geom::{Rect, Vec2};
+
+use crate::occlusion;
+use crate::optimize::{self, OptimizeOptions, OptimizeReport};
+use crate::paper::Paper;
+use crate::plotter::Plotter;
+use crate::scene::{Scene, Shape, Units};
+use crate::units::Mm;
+
+/// Slack allowed when checking geometry against the plot area, in millimetres.
+const TOLERANCE: f32 = 1e-3;
+/// Pen paths for dots are polygons with this many sides.
+const DOT_RESOLUTION: usize = 12;
+
+/// The part of a sheet of paper the plotter may draw on.
+pub struct PlotArea {
+    pub paper: Paper,
+    /// Blank border kept on every side of the paper.
+    pub margin: Mm,
+}
+
+impl PlotArea {
+    pub fn new(paper: Paper, margin: Mm) -> Self {
+        PlotArea { paper, margin }
+    }
+
+    /// The drawable rectangle in plot coordinates.
+    pub fn rect(&self) -> Rect {
+        let (w, h) = (self.paper.width().as_f32(), self.paper.height().as_f32());
+        let margin = self.margin.as_f32();
+        Rect::from_corners(Vec2::new(margin, margin), Vec2::new(w - margin, h - margin))
+    }
+}
+
+#[derive(Debug)]
+pub enum PlotError {
+    /// The margins leave no room to draw on the paper.
+    NoDrawableArea,
+    /// Some geometry lands outside the drawable area. All extents are in millimetres.
+    OutOfBounds { geometry: Rect, area: Rect },
+    /// Some geometry lands outside the plotter's bed, given in plot coordinates.
+    OutsideBed {
+        plotter: Plotter,
+        geometry: Rect,
+        bed: Rect,
+    },
+    /// A feed rate is outside what the plotter can do, in millimetres per minute. `name` says
+    /// which one, such as `drawing`.
+    FeedOutOfRange {
+        plotter: Plotter,
+        name: &'static str,
+        feed: f32,
+        min: f32,
+        max: f32,
+    },
+}
+
+impl fmt::Display for PlotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlotError::NoDrawableArea => {
+                write!(f, "the margins leave no drawable area on the paper")
+            }
+            PlotError::OutOfBounds { geometry, area } => write!(
+                f,
+                "geometry spans {}, which falls outside the plot area {} ({})",
+                extents(geometry),
+                extents(area),
+                overshoot(geometry, area)
+            ),
+            PlotError::OutsideBed {
+                plotter,
+                geometry,
+                bed,
+            } => write!(
+                f,
+                "geometry spans {}, which falls outside the {} bed at {} ({})",
+                extents(geometry),
+                plotter,
+                extents(bed),
+                overshoot(geometry, bed)
+            ),
+            PlotError::FeedOutOfRange {
+                plotter,
+                name,
+                feed,
+                min,
+                max,
+            } => write!(
+                f,
+                "a {} feed of {} mm/min is outside the {} range of {}..{} mm/min",
+                name, feed, plotter, min, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PlotError {}
+
+/// Pen paths on paper. Each path is drawn with the pen down from its first point to its last.
+pub struct Plot {
+    pub paper_w: f32,
+    pub paper_h: f32,
+    pub paths: Vec<Vec<Vec2>>,
+}
+
+impl Plot {
+    /// Place the scene on the paper and keep it inside the plot area, see `place` and `confine`.
+    pub fn from_scene(scene: &Scene, area: &PlotArea, clip: bool) -> Result<Self, PlotError> {
+        Plot::place(scene, area)?.confine(area, clip)
+    }
+
+    /// Place the scene on the paper. A scene in millimetres is drawn at its true size, centred on
+    /// the paper, while any other scene is scaled to fit the plot area, keeping its aspect ratio
+    /// and centring it. Lines covered by opaque fills drawn after them are left out, see
+    /// `occlusion`.
+    ///
+    /// Nothing is checked against the plot area yet, so the paths keep their full extent.
+    pub fn place(scene: &Scene, area: &PlotArea) -> Result<Self, PlotError> {
+        let area_rect = area.rect();
+        if area_rect.w() <= 0.0 || area_rect.h() <= 0.0 {
+            return Err(PlotError::NoDrawableArea);
+        }
+        let (paper_w, paper_h) = (area.paper.width().as_f32(), area.paper.height().as_f32());
+        let (centre, scale) = match scene.units {
+            Units::Mm => (Vec2::new(paper_w, paper_h) * 0.5, 1.0),
+            Units::Pixels => (
+                area_rect.xy(),
+                (area_rect.w() / scene.rect.w()).min(area_rect.h() / scene.rect.h()),
+            ),
+        };
+        let to_paper = |p: Vec2| centre + (p - scene.rect.xy()) * scale;
+
+        let paths: Vec<Vec<Vec2>> = occlusion::visible_paths(scene)
+            .into_iter()
+            .map(|path| path.into_iter().map(to_paper).collect())
+            .collect();
+
+        Ok(Plot {
+            paper_w,
+            paper_h,
+            paths,
+        })
+    }
+
+    /// Anything outside the plot area is an error unless `clip` is set, in which case such
+    /// geometry is cut off at the edge of the plot area.
+    pub fn confine(mut self, area: &PlotArea, clip: bool) -> Result<Self, PlotError> {
+        let area_rect = area.rect();
+        if clip {
+            self.paths = self
+                .paths
+                .iter()
+                .flat_map(|path| clip_path(path, &area_rect))
+                .collect();
+        } else if let Some(geometry) = self.bounds() {
+            if exceeds(&geometry, &area_rect) {
+                return Err(PlotError::OutOfBounds {
+                    geometry,
+                    area: area_rect,
+                });
+            }
+        }
+        Ok(self)
+    }
+
+    /// The extent of every path, or `None` if there is nothing to draw.
+    pub fn bounds(&self) -> Option<Rect> {
+        bounds(&self.paths)
+    }
+
+    /// Reorder the paths to cut down on pen-up travel, see `optimize`.
+    pub fn optimize(&mut self, options: &OptimizeOptions) -> OptimizeReport {
+        let (paths, report) = optimize::optimize(std::mem::take(&mut self.paths), options);
+        self.paths = paths;
+        report
+    }
+}
+
+/// Every line in the scene as an open path in scene units. Closed outlines repeat their first point.
+pub fn pen_paths(scene: &Scene) -> Vec<Vec<Vec2>> {
+    scene
+        .shapes
+        .iter()
+        .map(pen_path)
+        .filter(|path: &Vec<Vec2>| path.len() > 1)
+        .collect()
+}
+
+/// The line the pen follows to draw `shape`: a polyline as is, or the closed outline of anything else.
+pub(crate) fn pen_path(shape: &Shape) -> Vec<Vec2> {
+    match shape {
+        Shape::Polyline { points, .. } => points.clone(),
+        Shape::Polygon { points, .. } => closed(points.clone()),
+        Shape::Ellipse { xy, wh, .. } => {
+            let outline = (0..DOT_RESOLUTION)
+                .map(|i| {
+                    let angle = i as f32 / DOT_RESOLUTION as f32 * std::f32::consts::TAU;
+                    *xy + Vec2::new(angle.cos() * wh.x, angle.sin() * wh.y) * 0.5
+                })
+                .collect();
+            closed(outline)
+        }
+    }
+}
+
+fn closed(mut points: Vec<Vec2>) -> Vec<Vec2> {
+    if let Some(&first) = points.first() {
+        points.push(first);
+    }
+    points
+}
+
+pub(crate) fn bounds(paths: &[Vec<Vec2>]) -> Option<Rect> {
+    let mut points = paths.iter().flatten();
+    let first = *points.next()?;
+    let (min, max) = points.fold((first, first), |(min, max), &p| (min.min(p), max.max(p)));
+    Some(Rect::from_corners(min, max))
+}
+
+/// Whether `geometry` reaches further than `area` on any side.
+pub(crate) fn exceeds(geometry: &Rect, area: &Rect) -> bool {
+    geometry.left() < area.left() - TOLERANCE
+        || geometry.right() > area.right() + TOLERANCE
+        || geometry.bottom() < area.bottom() - TOLERANCE
+        || geometry.top() > area.top() + TOLERANCE
+}
+
+fn extents(rect: &Rect) -> String {
+    format!(
+        "x {:.2}..{:.2} mm, y {:.2}..{:.2} mm",
+        rect.left(),
+        rect.right(),
+        rect.bottom(),
+        rect.top()
+    )
+}
+
+/// How far `geometry` runs past each side of `area`, such as `3.20 mm past the left`.
+fn overshoot(geometry: &Rect, area: &Rect) -> String {
+    let sides = [
+        ("left", area.left() - geometry.left()),
+        ("right", geometry.right() - area.right()),
+        ("bottom", area.bottom() - geometry.bottom()),
+        ("top", geometry.top() - area.top()),
+    ];
+    sides
+        .iter()
+        .filter(|(_, by)| *by > TOLERANCE)
+        .map(|(side, by)| format!("{:.2} mm past the {}", by, side))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Cut a path against `rect`, splitting it wherever it leaves and re-enters.
+pub(crate) fn clip_path(path: &[Vec2], rect: &Rect) -> Vec<Vec<Vec2>> {
+    let mut clipped: Vec<Vec<Vec2>> = Vec::new();
+    let mut current: Vec<Vec2> = Vec::new();
+    for segment in path.windows(2) {
+        match clip_segment(segment[0], segment[1], rect) {
+            Some((a, b)) => {
+                if current.last() != Some(&a) {
+                    if current.len() > 1 {
+                        clipped.push(std::mem::take(&mut current));
+                    }
+                    current = vec![a];
+                }
+                current.push(b);
+            }
+            None => {
+                if current.len() > 1 {
+                    clipped.push(std::mem::take(&mut current));
+                }
+                current.clear();
+            }
+        }
+    }
+    if current.len() > 1 {
+        clipped.push(current);
+    }
+    clipped
+}
+
+/// Liang-Barsky clipping of the segment `a`-`b` against `rect`.
+fn clip_segment(a: Vec2, b: Vec2, rect: &Rect) -> Option<(Vec2, Vec2)> {
+    let d = b - a;
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
+    let checks = [
+        (-d.x, a.x - rect.left()),
+        (d.x, rect.right() - a.x),
+        (-d.y, a.y - rect.bottom()),
+        (d.y, rect.top() - a.y),
+    ];
+    for (p, q) in checks {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    let start = if t0 > 0.0 { a + d * t0 } else { a };
+    let end = if t1 < 1.0 { a + d * t1 } else { b };
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::paper::{Orientation, Paper, ISO216};
+    use crate::plot::{Plot, PlotArea, PlotError};
+    use crate::scene::Scene;
+    use crate::units::Mm;
+    use nannou::color::{Srgb, Srgba};
+    use nannou::geom::{Rect, Vec2};
+
+    fn a4_area() -> PlotArea {
+        let paper = Paper::from_iso216(ISO216::A4, Orientation::Portrait);
+        PlotArea::new(paper, Mm(10.0))
+    }
+
+    fn scene_with_line(from: Vec2, to: Vec2) -> Scene {
+        let mut scene = Scene::new(Rect::from_w_h(100.0, 100.0), Srgb::new(255u8, 255, 255));
+        scene.polyline(vec![from, to], Srgba::new(0u8, 0, 0, 255), 1.0);
+        scene
+    }
+
+    #[test]
+    fn scene_is_scaled_into_the_plot_area() {
+        // A diagonal across the whole square scene.
+        let scene = scene_with_line(Vec2::new(-50.0, -50.0), Vec2::new(50.0, 50.0));
+        let plot = Plot::from_scene(&scene, &a4_area(), false).unwrap();
+
+        // The 190 mm wide drawable area limits the scale, and the square is centred vertically.
+        let path = &plot.paths[0];
+        assert!(
+            (path[0] - Vec2::new(10.0, 53.5)).length() < 1e-3,
+            "{:?}",
+            path
+        );
+        assert!(
+            (path[1] - Vec2::new(200.0, 243.5)).length() < 1e-3,
+            "{:?}",
+            path
+        );
+    }
+
+    #[test]
+    fn millimetre_scenes_are_plotted_at_their_true_size() {
+        // A 100 mm line across the middle of a 100 mm square, centred on the sheet.
+        let scene = scene_with_line(Vec2::new(-50.0, 0.0), Vec2::new(50.0, 0.0)).in_mm();
+        let plot = Plot::from_scene(&scene, &a4_area(), false).unwrap();
+
+        let path = &plot.paths[0];
+        assert!(
+            (path[0] - Vec2::new(55.0, 148.5)).length() < 1e-3,
+            "{:?}",
+            path
+        );
+        assert!(
+            (path[1] - Vec2::new(155.0, 148.5)).length() < 1e-3,
+            "{:?}",
+            path
+        );
+    }
+
+    #[test]
+    fn millimetre_scenes_too_big_for_the_area_are_refused() {
+        // Scaling would make this fit, but 200 mm does not fit in the 190 mm wide drawable area.
+        let scene = scene_with_line(Vec2::new(-100.0, 0.0), Vec2::new(100.0, 0.0)).in_mm();
+        assert!(matches!(
+            Plot::from_scene(&scene, &a4_area(), false),
+            Err(PlotError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn geometry_outside_the_area_is_refused() {
+        let scene = scene_with_line(Vec2::new(0.0, 0.0), Vec2::new(80.0, 0.0));
+        match Plot::from_scene(&scene, &a4_area(), false) {
+            Err(err @ PlotError::OutOfBounds { .. }) => {
+                // The line runs from the middle of the square scene to 30 units past its right edge.
+                assert_eq!(
+                    err.to_string(),
+                    "geometry spans x 105.00..257.00 mm, y 148.50..148.50 mm, which falls outside \
+                     the plot area x 10.00..200.00 mm, y 10.00..287.00 mm (57.00 mm past the right)"
+                );
+            }
+            _ => panic!("expected an out of bounds error"),
+        }
+    }
+
+    #[test]
+    fn clipping_cuts_geometry_at_the_area_edge() {
+        let scene = scene_with_line(Vec2::new(0.0, 0.0), Vec2::new(80.0, 0.0));
+        let plot = Plot::from_scene(&scene, &a4_area(), true).unwrap();
+        assert_eq!(plot.paths.len(), 1);
+        assert!((plot.paths[0][1].x - 200.0).abs() < 1e-3);
+    }
+}
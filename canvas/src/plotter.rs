@@ -0,0 +1,280 @@
+//! Named pen plotters and the limits a plot has to respect on each of them.
+//!
+//! Pass `--plotter axidraw-v3` (or `v3-a3`, `se-a1`, `grbl`) when exporting a plot to have it
+//! checked against that machine's bed and speeds before anything is written, and written in the
+//! machine's own coordinates.
+
+use std::fmt;
+use std::str::FromStr;
+
+use nannou::geom::{Rect, Vec2};
+
+use crate::gcode::GcodeSettings;
+use crate::plot::{exceeds, Plot, PlotError};
+use crate::units::{Inch, Mm};
+
+/// The corner of the bed the plotter homes to. The paper is lined up against it, and the machine
+/// measures x to the right of it and y from it into the sheet.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Origin {
+    TopLeft,
+    BottomLeft,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Plotter {
+    AxiDrawV3,
+    AxiDrawV3A3,
+    AxiDrawSeA1,
+    /// A generic GRBL machine with a 300 mm square bed.
+    Grbl,
+}
+
+impl Plotter {
+    /// The width and height of the area the pen can reach.
+    pub fn bed(&self) -> (Mm, Mm) {
+        match self {
+            Plotter::AxiDrawV3 => (Inch(11.81).into(), Inch(8.58).into()),
+            Plotter::AxiDrawV3A3 => (Inch(16.93).into(), Inch(11.69).into()),
+            Plotter::AxiDrawSeA1 => (Inch(34.02).into(), Inch(23.39).into()),
+            Plotter::Grbl => (Mm(300.0), Mm(300.0)),
+        }
+    }
+
+    pub fn origin(&self) -> Origin {
+        match self {
+            Plotter::AxiDrawV3 | Plotter::AxiDrawV3A3 | Plotter::AxiDrawSeA1 => Origin::TopLeft,
+            Plotter::Grbl => Origin::BottomLeft,
+        }
+    }
+
+    /// The slowest and fastest drawing speeds, in millimetres per minute.
+    pub fn speeds(&self) -> (f32, f32) {
+        match self {
+            Plotter::AxiDrawV3 | Plotter::AxiDrawV3A3 => (60.0, 22_860.0),
+            Plotter::AxiDrawSeA1 => (60.0, 15_240.0),
+            Plotter::Grbl => (10.0, 5_000.0),
+        }
+    }
+
+    /// Where the pen starts, the origin corner of a sheet `paper_h` millimetres tall, in plot
+    /// coordinates.
+    pub fn home(&self, paper_h: f32) -> Vec2 {
+        match self.origin() {
+            Origin::TopLeft => Vec2::new(0.0, paper_h),
+            Origin::BottomLeft => Vec2::ZERO,
+        }
+    }
+
+    /// The bed in plot coordinates, for a sheet `paper_h` millimetres tall lined up against the
+    /// origin corner.
+    pub fn bed_rect(&self, paper_h: f32) -> Rect {
+        let (w, h) = self.bed();
+        let (w, h) = (w.as_f32(), h.as_f32());
+        let (left, bottom) = match self.origin() {
+            Origin::TopLeft => (0.0, paper_h - h),
+            Origin::BottomLeft => (0.0, 0.0),
+        };
+        Rect::from_corners(Vec2::new(left, bottom), Vec2::new(left + w, bottom + h))
+    }
+
+    /// `plot` in the coordinates the machine moves in. Plots run from the bottom left corner of the
+    /// sheet with y up, so on a plotter homing top left y is measured down from the top instead.
+    pub fn to_machine(&self, plot: &Plot) -> Plot {
+        let paths = match self.origin() {
+            Origin::TopLeft => plot
+                .paths
+                .iter()
+                .map(|path| {
+                    path.iter()
+                        .map(|p| Vec2::new(p.x, plot.paper_h - p.y))
+                        .collect()
+                })
+                .collect(),
+            Origin::BottomLeft => plot.paths.clone(),
+        };
+        Plot {
+            paper_w: plot.paper_w,
+            paper_h: plot.paper_h,
+            paths,
+        }
+    }
+
+    /// Refuse a plot that reaches past the bed or would be drawn or lowered at a feed the plotter
+    /// cannot do. Check the plot before it is confined to the plot area, see `Plot::place`, so that
+    /// a job too big for the machine is refused rather than cut down to fit.
+    pub fn check(&self, plot: &Plot, settings: &GcodeSettings) -> Result<(), PlotError> {
+        let bed = self.bed_rect(plot.paper_h);
+        if let Some(geometry) = plot.bounds() {
+            if exceeds(&geometry, &bed) {
+                return Err(PlotError::OutsideBed {
+                    plotter: *self,
+                    geometry,
+                    bed,
+                });
+            }
+        }
+        let (min, max) = self.speeds();
+        for (name, feed) in [("drawing", settings.draw_feed), ("pen", settings.pen_feed)] {
+            if feed < min || feed > max {
+                return Err(PlotError::FeedOutOfRange {
+                    plotter: *self,
+                    name,
+                    feed,
+                    min,
+                    max,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Plotter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Plotter::AxiDrawV3 => write!(f, "AxiDraw V3"),
+            Plotter::AxiDrawV3A3 => write!(f, "AxiDraw V3/A3"),
+            Plotter::AxiDrawSeA1 => write!(f, "AxiDraw SE/A1"),
+            Plotter::Grbl => write!(f, "GRBL"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PlotterParseErr {
+    UnknownPlotterErr(String),
+}
+
+impl fmt::Display for PlotterParseErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlotterParseErr::UnknownPlotterErr(s) => write!(
+                f,
+                "unknown plotter {:?}, expected axidraw-v3, axidraw-v3-a3, axidraw-se-a1 or grbl",
+                s
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PlotterParseErr {}
+
+impl FromStr for Plotter {
+    type Err = PlotterParseErr;
+
+    /// Parse a plotter name such as `axidraw-v3`, `V3/A3`, `se-a1` or `grbl`. Names ignore case,
+    /// spaces, hyphens and slashes, and the `axidraw` prefix is optional.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name: String = s
+            .chars()
+            .filter(|c| !matches!(c, '-' | ' ' | '_' | '/'))
+            .collect::<String>()
+            .to_lowercase();
+        match name.strip_prefix("axidraw").unwrap_or(&name) {
+            "v3" => Ok(Plotter::AxiDrawV3),
+            "v3a3" => Ok(Plotter::AxiDrawV3A3),
+            "sea1" => Ok(Plotter::AxiDrawSeA1),
+            "grbl" => Ok(Plotter::Grbl),
+            _ => Err(PlotterParseErr::UnknownPlotterErr(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gcode::GcodeSettings;
+    use crate::plot::{Plot, PlotError};
+    use crate::plotter::Plotter;
+    use nannou::geom::Vec2;
+
+    fn plot_with_line(from: Vec2, to: Vec2) -> Plot {
+        // A4 landscape.
+        Plot {
+            paper_w: 297.0,
+            paper_h: 210.0,
+            paths: vec![vec![from, to]],
+        }
+    }
+
+    #[test]
+    fn parse_names() {
+        assert_eq!("axidraw-v3".parse(), Ok(Plotter::AxiDrawV3));
+        assert_eq!("V3/A3".parse(), Ok(Plotter::AxiDrawV3A3));
+        assert_eq!("AxiDraw SE/A1".parse(), Ok(Plotter::AxiDrawSeA1));
+        assert_eq!("GRBL".parse(), Ok(Plotter::Grbl));
+        assert!("v4".parse::<Plotter>().is_err());
+    }
+
+    #[test]
+    fn bed_hangs_from_the_origin_corner() {
+        // The AxiDraw homes top left, so its 217.9 mm tall bed reaches down from the top of the sheet.
+        let bed = Plotter::AxiDrawV3.bed_rect(297.0);
+        assert!((bed.top() - 297.0).abs() < 1e-3);
+        assert!((bed.bottom() - 79.068).abs() < 1e-3);
+        assert_eq!(bed.left(), 0.0);
+
+        let bed = Plotter::Grbl.bed_rect(297.0);
+        assert_eq!((bed.left(), bed.bottom()), (0.0, 0.0));
+    }
+
+    #[test]
+    fn pens_start_from_the_origin_corner() {
+        assert_eq!(Plotter::AxiDrawV3.home(297.0), Vec2::new(0.0, 297.0));
+        assert_eq!(Plotter::Grbl.home(297.0), Vec2::ZERO);
+    }
+
+    #[test]
+    fn plots_are_written_from_the_machine_origin() {
+        let plot = plot_with_line(Vec2::new(10.0, 20.0), Vec2::new(30.0, 200.0));
+        // The AxiDraw's y runs down the sheet from its top edge.
+        assert_eq!(
+            Plotter::AxiDrawV3.to_machine(&plot).paths,
+            vec![vec![Vec2::new(10.0, 190.0), Vec2::new(30.0, 10.0)]]
+        );
+        assert_eq!(Plotter::Grbl.to_machine(&plot).paths, plot.paths);
+    }
+
+    #[test]
+    fn jobs_past_the_bed_are_refused() {
+        let fits = plot_with_line(Vec2::new(10.0, 10.0), Vec2::new(287.0, 200.0));
+        assert!(Plotter::AxiDrawV3
+            .check(&fits, &GcodeSettings::default())
+            .is_ok());
+
+        let wide = plot_with_line(Vec2::new(10.0, 10.0), Vec2::new(305.0, 200.0));
+        match Plotter::AxiDrawV3.check(&wide, &GcodeSettings::default()) {
+            Err(err @ PlotError::OutsideBed { .. }) => assert_eq!(
+                err.to_string(),
+                "geometry spans x 10.00..305.00 mm, y 10.00..200.00 mm, which falls outside the \
+                 AxiDraw V3 bed at x 0.00..299.97 mm, y -7.93..210.00 mm (5.03 mm past the right)"
+            ),
+            other => panic!("expected the bed to be exceeded, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn feeds_are_checked_against_the_plotter() {
+        let plot = plot_with_line(Vec2::new(10.0, 10.0), Vec2::new(20.0, 20.0));
+        let feeds = |draw_feed, pen_feed| GcodeSettings {
+            draw_feed,
+            pen_feed,
+            ..GcodeSettings::default()
+        };
+        assert!(Plotter::Grbl.check(&plot, &feeds(5000.0, 500.0)).is_ok());
+        assert!(matches!(
+            Plotter::Grbl.check(&plot, &feeds(6000.0, 500.0)),
+            Err(PlotError::FeedOutOfRange {
+                name: "drawing",
+                ..
+            })
+        ));
+        match Plotter::Grbl.check(&plot, &feeds(1500.0, 5.0)) {
+            Err(err @ PlotError::FeedOutOfRange { .. }) => assert_eq!(
+                err.to_string(),
+                "a pen feed of 5 mm/min is outside the GRBL range of 10..5000 mm/min"
+            ),
+            other => panic!("expected the pen feed to be refused, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,565 @@
+//! Lays a sketch out on a sheet for a print shop: the artwork fills the trim box, fills that touch
+//! its edges run on into the bleed, and crop marks, registration marks and a colour bar can be
+//! added in the slug around it.
+//!
+//! Pass `--print` to render the print page instead of the bare artwork. The trim size is the
+//! `--paper`, `--bleed-mm 5` sets the bleed (3 mm by default), `--crop-marks` adds crop and
+//! registration marks and `--color-bar` adds a colour bar. Page coordinates are millimetres with the
+//! origin at the centre of the trim box.
+
+use nannou::color::Srgb;
+use nannou::geom::{Rect, Vec2};
+
+use crate::args::Args;
+use crate::paper::Paper;
+use crate::plot::clip_path;
+use crate::scene::{rect_points, Scene, Shape, Stroke, Units};
+use crate::units::Mm;
+
+/// The bleed most print shops ask for.
+pub const DEFAULT_BLEED: Mm = Mm(3.0);
+/// Print pages are rendered at this many pixels per inch unless `--dpi` says otherwise.
+pub const DEFAULT_DPI: u16 = 300;
+
+/// Crop marks never start closer to the trim than this, so they stay clear of a small bleed.
+const MIN_MARK_OFFSET: Mm = Mm(3.0);
+const MARK_LENGTH: Mm = Mm(5.0);
+/// Hairline marks, 0.25 pt wide.
+const MARK_WEIGHT: Mm = Mm(0.088);
+/// Blank paper kept between the marks and the edge of the sheet.
+const SLUG_MARGIN: Mm = Mm(2.0);
+const REGISTRATION_RADIUS: Mm = Mm(1.5);
+const REGISTRATION_RESOLUTION: usize = 32;
+/// Ellipses cut by the bleed are clipped as polygons with this many sides.
+const ELLIPSE_RESOLUTION: usize = 32;
+const SWATCH_SIZE: Mm = Mm(5.0);
+/// Vertices closer than this to an edge of the artwork are treated as lying on it, in millimetres.
+const EDGE_TOLERANCE: f32 = 1e-3;
+
+const WHITE: (u8, u8, u8) = (255, 255, 255);
+/// The colour marks show in. They are flagged as registration so the PDF prints them on every plate.
+const MARK_COLOR: (u8, u8, u8) = (0, 0, 0);
+/// Process colours, overprints and a grey ramp, left to right.
+const SWATCHES: [(u8, u8, u8); 11] = [
+    (0, 255, 255),
+    (255, 0, 255),
+    (255, 255, 0),
+    (0, 0, 0),
+    (255, 0, 0),
+    (0, 255, 0),
+    (0, 0, 255),
+    (191, 191, 191),
+    (128, 128, 128),
+    (64, 64, 64),
+    (255, 255, 255),
+];
+
+/// How the artwork sits on the printed sheet.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PrintLayout {
+    /// The finished size the sheet is trimmed to.
+    pub paper: Paper,
+    /// How far edge-touching fills run on past the trim on every side.
+    pub bleed: Mm,
+    /// Crop marks at the corners and registration marks at the middle of each side.
+    pub crop_marks: bool,
+    pub color_bar: bool,
+}
+
+impl PrintLayout {
+    /// A layout trimmed to `paper` with the default bleed and no marks.
+    pub fn new(paper: Paper) -> Self {
+        PrintLayout {
+            paper,
+            bleed: DEFAULT_BLEED,
+            crop_marks: false,
+            color_bar: false,
+        }
+    }
+
+    /// The layout asked for with `--print`, or `None` when the bare artwork is wanted.
+    pub fn from_args(args: &Args) -> Option<Self> {
+        if !args.flag("print") {
+            return None;
+        }
+        Some(PrintLayout {
+            paper: Paper::from_args(args),
+            bleed: Mm(args.parsed("bleed-mm").unwrap_or(DEFAULT_BLEED.0)),
+            crop_marks: args.flag("crop-marks"),
+            color_bar: args.flag("color-bar"),
+        })
+    }
+
+    /// The finished page.
+    pub fn trim_box(&self) -> Rect {
+        Rect::from_w_h(self.paper.width().as_f32(), self.paper.height().as_f32())
+    }
+
+    /// The trim box grown by the bleed. Nothing of the artwork is drawn outside it.
+    pub fn bleed_box(&self) -> Rect {
+        self.trim_box().pad(-self.bleed.as_f32())
+    }
+
+    /// The whole sheet, with room for the marks and colour bar when they are asked for.
+    pub fn media_box(&self) -> Rect {
+        if self.crop_marks || self.color_bar {
+            self.trim_box()
+                .pad(-(self.mark_offset() + MARK_LENGTH + SLUG_MARGIN).as_f32())
+        } else {
+            self.bleed_box()
+        }
+    }
+
+    /// The media box in whole pixels at `dpi`, rounded to the nearest pixel.
+    pub fn pixel_dimensions(&self, dpi: u16) -> (u32, u32) {
+        let media = self.media_box();
+        let dpi = dpi as f64;
+        (
+            Mm(media.w() as f64).to_px(dpi).round() as u32,
+            Mm(media.h() as f64).to_px(dpi).round() as u32,
+        )
+    }
+
+    /// The distance from the trim to where the marks start.
+    fn mark_offset(&self) -> Mm {
+        self.bleed.max(MIN_MARK_OFFSET)
+    }
+
+    /// Place `art` on the sheet. The artwork is scaled to fit the trim box, keeping its aspect ratio
+    /// and centring it, and its background and any filled polygon touching a trimmed edge are
+    /// extended into the bleed.
+    pub fn compose(&self, art: &Scene) -> Scene {
+        let trim = self.trim_box();
+        let bleed = self.bleed_box();
+        let scale = (trim.w() / art.rect.w()).min(trim.h() / art.rect.h());
+        let to_page = |p: Vec2| (p - art.rect.xy()) * scale;
+        let placed = Rect::from_w_h(art.rect.w() * scale, art.rect.h() * scale);
+        let extend = Extension::new(&placed, &trim, &bleed);
+
+        let mut page = Scene {
+            rect: self.media_box(),
+            units: Units::Mm,
+            background: Srgb::from_components(WHITE).into(),
+            shapes: Vec::new(),
+        };
+        page.shapes.push(Shape::Polygon {
+            points: rect_points(&bleed),
+            fill: Some(art.background),
+            stroke: None,
+        });
+        for shape in &art.shapes {
+            match shape {
+                Shape::Polyline { points, stroke } => {
+                    let points: Vec<Vec2> = points.iter().map(|&p| to_page(p)).collect();
+                    for points in clip_path(&points, &bleed) {
+                        page.shapes.push(Shape::Polyline {
+                            points,
+                            stroke: scaled(stroke, scale),
+                        });
+                    }
+                }
+                Shape::Polygon {
+                    points,
+                    fill,
+                    stroke,
+                } => {
+                    let points: Vec<Vec2> = points
+                        .iter()
+                        .map(|&p| {
+                            let p = to_page(p);
+                            if fill.is_some() {
+                                extend.apply(p)
+                            } else {
+                                p
+                            }
+                        })
+                        .collect();
+                    let points = clip_polygon(&points, &bleed);
+                    if points.len() > 2 {
+                        page.shapes.push(Shape::Polygon {
+                            points,
+                            fill: *fill,
+                            stroke: stroke.map(|stroke| scaled(&stroke, scale)),
+                        });
+                    }
+                }
+                Shape::Ellipse { xy, wh, fill } => {
+                    let (xy, wh) = (to_page(*xy), *wh * scale);
+                    let extent = Rect::from_xy_wh(xy, wh);
+                    if inside(&extent, &bleed) {
+                        page.shapes.push(Shape::Ellipse {
+                            xy,
+                            wh,
+                            fill: *fill,
+                        });
+                    } else if extent.overlap(bleed).is_some() {
+                        // Cut by the bleed, so it is drawn as the part of its outline inside.
+                        let points = clip_polygon(&ellipse_points(xy, wh), &bleed);
+                        if points.len() > 2 {
+                            page.shapes.push(Shape::Polygon {
+                                points,
+                                fill: Some(*fill),
+                                stroke: None,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        if self.crop_marks {
+            self.draw_crop_marks(&mut page);
+            self.draw_registration_marks(&mut page);
+        }
+        if self.color_bar {
+            self.draw_color_bar(&mut page);
+        }
+        page
+    }
+
+    /// Two short lines at each corner, lined up with the trim and kept clear of the bleed.
+    fn draw_crop_marks(&self, page: &mut Scene) {
+        let trim = self.trim_box();
+        let (start, end) = (
+            self.mark_offset().as_f32(),
+            (self.mark_offset() + MARK_LENGTH).as_f32(),
+        );
+        for corner in rect_points(&trim) {
+            let out = Vec2::new(corner.x.signum(), corner.y.signum());
+            for direction in [Vec2::new(out.x, 0.0), Vec2::new(0.0, out.y)] {
+                mark(
+                    page,
+                    vec![corner + direction * start, corner + direction * end],
+                );
+            }
+        }
+    }
+
+    /// A target at the middle of each side, in the slug. The top one makes way for the colour bar.
+    fn draw_registration_marks(&self, page: &mut Scene) {
+        let trim = self.trim_box();
+        let distance = (self.mark_offset() + MARK_LENGTH / 2.0).as_f32();
+        let mut centres = vec![
+            Vec2::new(0.0, trim.bottom() - distance),
+            Vec2::new(trim.left() - distance, 0.0),
+            Vec2::new(trim.right() + distance, 0.0),
+        ];
+        if !self.color_bar {
+            centres.push(Vec2::new(0.0, trim.top() + distance));
+        }
+        let (radius, arm) = (REGISTRATION_RADIUS.as_f32(), (MARK_LENGTH / 2.0).as_f32());
+        for centre in centres {
+            let circle = (0..=REGISTRATION_RESOLUTION)
+                .map(|i| {
+                    let angle = i as f32 / REGISTRATION_RESOLUTION as f32 * std::f32::consts::TAU;
+                    centre + Vec2::new(angle.cos(), angle.sin()) * radius
+                })
+                .collect();
+            mark(page, circle);
+            mark(
+                page,
+                vec![centre - Vec2::new(arm, 0.0), centre + Vec2::new(arm, 0.0)],
+            );
+            mark(
+                page,
+                vec![centre - Vec2::new(0.0, arm), centre + Vec2::new(0.0, arm)],
+            );
+        }
+    }
+
+    /// A row of solid swatches in the top slug, starting level with the left trim.
+    fn draw_color_bar(&self, page: &mut Scene) {
+        let trim = self.trim_box();
+        let size = SWATCH_SIZE.as_f32();
+        let y = trim.top() + (self.mark_offset() + MARK_LENGTH / 2.0).as_f32();
+        for (i, &(r, g, b)) in SWATCHES.iter().enumerate() {
+            let x = trim.left() + size * (i as f32 + 0.5);
+            let swatch = Rect::from_x_y_w_h(x, y, size, size);
+            page.rect(&swatch, Srgb::new(r, g, b));
+            page.shapes.push(Shape::Polygon {
+                points: rect_points(&swatch),
+                fill: None,
+                stroke: Some(mark_stroke()),
+            });
+        }
+    }
+}
+
+/// A hairline mark printed on every plate.
+fn mark(page: &mut Scene, points: Vec<Vec2>) {
+    page.shapes.push(Shape::Polyline {
+        points,
+        stroke: mark_stroke(),
+    });
+}
+
+fn mark_stroke() -> Stroke {
+    Stroke {
+        color: Srgb::from_components(MARK_COLOR).into(),
+        weight: MARK_WEIGHT.as_f32(),
+        registration: true,
+    }
+}
+
+/// Render the print page rather than the bare artwork when `--print` is given, at `--dpi` (300 by
+/// default). Otherwise `art` and `size` are passed through untouched.
+pub fn requested(art: Scene, args: &Args, size: (u32, u32)) -> (Scene, (u32, u32)) {
+    match PrintLayout::from_args(args) {
+        Some(layout) => {
+            let dpi = args.parsed("dpi").unwrap_or(DEFAULT_DPI);
+            (layout.compose(&art), layout.pixel_dimensions(dpi))
+        }
+        None => (art, size),
+    }
+}
+
+/// Moves points on the edges of the placed artwork that coincide with the trim out to the bleed.
+struct Extension {
+    placed: Rect,
+    bleed: Rect,
+    sides: [bool; 4],
+}
+
+impl Extension {
+    fn new(placed: &Rect, trim: &Rect, bleed: &Rect) -> Self {
+        let touches = |a: f32, b: f32| (a - b).abs() < EDGE_TOLERANCE;
+        Extension {
+            placed: *placed,
+            bleed: *bleed,
+            sides: [
+                touches(placed.left(), trim.left()),
+                touches(placed.right(), trim.right()),
+                touches(placed.bottom(), trim.bottom()),
+                touches(placed.top(), trim.top()),
+            ],
+        }
+    }
+
+    fn apply(&self, mut p: Vec2) -> Vec2 {
+        let on = |a: f32, b: f32| (a - b).abs() < EDGE_TOLERANCE;
+        if self.sides[0] && on(p.x, self.placed.left()) {
+            p.x = self.bleed.left();
+        }
+        if self.sides[1] && on(p.x, self.placed.right()) {
+            p.x = self.bleed.right();
+        }
+        if self.sides[2] && on(p.y, self.placed.bottom()) {
+            p.y = self.bleed.bottom();
+        }
+        if self.sides[3] && on(p.y, self.placed.top()) {
+            p.y = self.bleed.top();
+        }
+        p
+    }
+}
+
+fn scaled(stroke: &Stroke, scale: f32) -> Stroke {
+    Stroke {
+        weight: stroke.weight * scale,
+        ..*stroke
+    }
+}
+
+/// Whether `extent` lies wholly within `rect`.
+fn inside(extent: &Rect, rect: &Rect) -> bool {
+    extent.left() >= rect.left()
+        && extent.right() <= rect.right()
+        && extent.bottom() >= rect.bottom()
+        && extent.top() <= rect.top()
+}
+
+/// The outline of the ellipse centred on `xy` with size `wh`, counter-clockwise.
+fn ellipse_points(xy: Vec2, wh: Vec2) -> Vec<Vec2> {
+    (0..ELLIPSE_RESOLUTION)
+        .map(|i| {
+            let angle = i as f32 / ELLIPSE_RESOLUTION as f32 * std::f32::consts::TAU;
+            xy + Vec2::new(angle.cos() * wh.x, angle.sin() * wh.y) * 0.5
+        })
+        .collect()
+}
+
+/// Sutherland-Hodgman clipping of a polygon against `rect`, one side at a time.
+fn clip_polygon(points: &[Vec2], rect: &Rect) -> Vec<Vec2> {
+    let sides = [
+        (0, rect.left(), false),
+        (0, rect.right(), true),
+        (1, rect.bottom(), false),
+        (1, rect.top(), true),
+    ];
+    let mut output = points.to_vec();
+    for (axis, bound, below) in sides {
+        let input = std::mem::take(&mut output);
+        let inside = |p: Vec2| {
+            if below {
+                p[axis] <= bound
+            } else {
+                p[axis] >= bound
+            }
+        };
+        let crossing = |a: Vec2, b: Vec2| a + (b - a) * ((bound - a[axis]) / (b[axis] - a[axis]));
+        for (i, &current) in input.iter().enumerate() {
+            let previous = input[(i + input.len() - 1) % input.len()];
+            if inside(current) {
+                if !inside(previous) {
+                    output.push(crossing(previous, current));
+                }
+                output.push(current);
+            } else if inside(previous) {
+                output.push(crossing(previous, current));
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::paper::{Orientation, Paper, PaperSize};
+    use crate::pdf::{self, PdfOptions};
+    use crate::print::PrintLayout;
+    use crate::scene::{Scene, Shape};
+    use crate::units::Mm;
+    use nannou::color::Srgb;
+    use nannou::geom::{Rect, Vec2};
+
+    fn a4() -> PrintLayout {
+        PrintLayout::new(Paper::new(PaperSize::a(4).unwrap(), Orientation::Portrait))
+    }
+
+    #[test]
+    fn boxes_nest_around_the_trim() {
+        let mut layout = a4();
+        assert_eq!(layout.trim_box().w(), 210.0);
+        assert_eq!(layout.bleed_box().w(), 216.0);
+        assert_eq!(layout.bleed_box().h(), 303.0);
+        assert_eq!(layout.media_box(), layout.bleed_box());
+
+        layout.crop_marks = true;
+        // A 3 mm offset, 5 mm marks and 2 mm of blank paper on every side.
+        assert_eq!(layout.media_box().w(), 230.0);
+        assert_eq!(layout.media_box().h(), 317.0);
+        assert_eq!(layout.pixel_dimensions(254), (2300, 3170));
+    }
+
+    #[test]
+    fn art_fits_the_trim_and_edge_fills_bleed() {
+        // A square in a landscape scene: the left half is filled and touches three edges.
+        let mut art = Scene::new(Rect::from_w_h(400.0, 300.0), Srgb::new(255u8, 255, 255));
+        art.rect(
+            &Rect::from_corners(Vec2::new(-200.0, -150.0), Vec2::new(0.0, 150.0)),
+            Srgb::new(255u8, 0, 0),
+        );
+        let page = a4().compose(&art);
+
+        // The art is 210 mm wide and 157.5 mm tall, so only its sides meet the trim.
+        let points = match &page.shapes[1] {
+            Shape::Polygon { points, .. } => points.clone(),
+            shape => panic!("expected a polygon, got {:?}", shape),
+        };
+        let bounds = points
+            .iter()
+            .fold((points[0], points[0]), |(min, max), &p| {
+                (min.min(p), max.max(p))
+            });
+        assert_eq!(bounds.0, Vec2::new(-108.0, -78.75));
+        assert_eq!(bounds.1, Vec2::new(0.0, 78.75));
+    }
+
+    #[test]
+    fn stray_geometry_is_cut_at_the_bleed() {
+        let mut art = Scene::new(Rect::from_w_h(210.0, 297.0), Srgb::new(255u8, 255, 255));
+        art.polyline(
+            vec![Vec2::new(0.0, 0.0), Vec2::new(500.0, 0.0)],
+            Srgb::new(0u8, 0, 0),
+            1.0,
+        );
+        art.ellipse(Vec2::new(0.0, 200.0), 1.0, Srgb::new(0u8, 0, 0));
+        let mut layout = a4();
+        layout.bleed = Mm(5.0);
+        let page = layout.compose(&art);
+
+        assert_eq!(page.shapes.len(), 2);
+        match &page.shapes[1] {
+            Shape::Polyline { points, .. } => assert_eq!(points[1], Vec2::new(110.0, 0.0)),
+            shape => panic!("expected a polyline, got {:?}", shape),
+        }
+    }
+
+    #[test]
+    fn ellipses_are_kept_or_cut_by_their_extent() {
+        let mut art = Scene::new(Rect::from_w_h(210.0, 297.0), Srgb::new(255u8, 255, 255));
+        // Its centre is past the 3 mm bleed on the right but its left half reaches inside.
+        art.ellipse(Vec2::new(109.0, 0.0), 4.0, Srgb::new(0u8, 0, 0));
+        // Centred inside the bleed, but its top pokes out of it.
+        art.ellipse(Vec2::new(0.0, 150.0), 2.0, Srgb::new(0u8, 0, 0));
+        // Wholly inside.
+        art.ellipse(Vec2::new(0.0, 0.0), 2.0, Srgb::new(0u8, 0, 0));
+        // Wholly outside.
+        art.ellipse(Vec2::new(0.0, 160.0), 2.0, Srgb::new(0u8, 0, 0));
+        let layout = a4();
+        let page = layout.compose(&art);
+        let bleed = layout.bleed_box();
+
+        assert_eq!(page.shapes.len(), 4);
+        for shape in &page.shapes[1..3] {
+            match shape {
+                Shape::Polygon { points, .. } => {
+                    for point in points {
+                        assert!(bleed.contains(*point), "{:?}", point);
+                    }
+                }
+                shape => panic!("expected a clipped polygon, got {:?}", shape),
+            }
+        }
+        assert!(matches!(page.shapes[3], Shape::Ellipse { .. }));
+    }
+
+    #[test]
+    fn marks_sit_in_the_slug() {
+        let mut layout = a4();
+        layout.crop_marks = true;
+        layout.color_bar = true;
+        let art = Scene::new(Rect::from_w_h(210.0, 297.0), Srgb::new(255u8, 255, 255));
+        let page = layout.compose(&art);
+        let trim = layout.trim_box();
+        let media = layout.media_box();
+
+        let marks: Vec<&Vec<Vec2>> = page
+            .shapes
+            .iter()
+            .filter_map(|shape| match shape {
+                Shape::Polyline { points, .. } => Some(points),
+                _ => None,
+            })
+            .collect();
+        // Eight crop marks and three registration targets of three lines each.
+        assert_eq!(marks.len(), 8 + 3 * 3);
+        for point in marks.into_iter().flatten() {
+            assert!(media.contains(*point), "{:?}", point);
+            let inside_trim =
+                point.x.abs() < trim.right() - 1e-3 && point.y.abs() < trim.top() - 1e-3;
+            assert!(!inside_trim, "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn marks_print_on_every_plate() {
+        let mut layout = a4();
+        layout.crop_marks = true;
+        let mut art = Scene::new(Rect::from_w_h(210.0, 297.0), Srgb::new(255u8, 255, 255));
+        art.rect(&Rect::from_w_h(20.0, 20.0), Srgb::new(0u8, 0, 0));
+        let page = layout.compose(&art);
+        let media = layout.media_box();
+        let options = PdfOptions {
+            cmyk: true,
+            ..PdfOptions::default()
+        };
+        let pdf = pdf::render(&page, Mm(media.w() as f64), Mm(media.h() as f64), &options);
+        let pdf = String::from_utf8(pdf).unwrap();
+
+        assert!(pdf.contains("[/Separation /All /DeviceCMYK << /FunctionType 2 /Domain [0 1] /C0 [0 0 0 0] /C1 [1 1 1 1] /N 1 >>]"), "{}", pdf);
+        assert!(pdf.contains("/CS0 CS 1 SCN"), "{}", pdf);
+        // Black artwork stays on the black plate.
+        assert!(pdf.contains("0 0 0 1 k"), "{}", pdf);
+    }
+}
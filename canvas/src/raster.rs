@@ -0,0 +1,369 @@
+//! A software rasterizer for `Scene`s, used where there is no GPU or window to render with.
+//!
+//! Every shape is turned into closed outlines in pixel space, the signed area of those outlines is
+//! accumulated per pixel and the resulting coverage is blended over the image. Strokes are built from
+//! one quad per segment plus a round join at every vertex, all wound the same way so that overlapping
+//! pieces merge instead of cancelling out.
+
+use std::path::Path;
+
+use image::{ImageResult, Rgba, RgbaImage};
+use nannou::color::Srgba;
+use nannou::geom::Vec2;
+
+use crate::scene::{Scene, Shape, Stroke};
+
+/// Coverage below this is invisible once quantized to 8 bits.
+const MIN_COVERAGE: f32 = 1.0 / 512.0;
+
+/// Render the whole scene into an image of `width` x `height` pixels.
+pub fn render(scene: &Scene, width: u32, height: u32) -> RgbaImage {
+    render_region(scene, width, (0, 0, width, height))
+}
+
+/// Render one window of the image `render` would make at `width` pixels wide: the `w` x `h` pixels
+/// whose top left corner is at `x`, `y`. Windows side by side join up exactly.
+pub fn render_region(scene: &Scene, width: u32, (x, y, w, h): (u32, u32, u32, u32)) -> RgbaImage {
+    let scale = width as f32 / scene.rect.w();
+    let transform = Transform {
+        scale,
+        left: scene.rect.left() + x as f32 / scale,
+        top: scene.rect.top() - y as f32 / scale,
+    };
+    let mut image = RgbaImage::from_pixel(w, h, to_pixel(scene.background));
+    for shape in &scene.shapes {
+        draw_shape(&mut image, &transform, shape);
+    }
+    image
+}
+
+/// Render the scene and write it to a PNG at `path`, creating the parent directory if needed.
+pub fn save(scene: &Scene, width: u32, height: u32, path: &Path) -> ImageResult<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    render(scene, width, height).save(path)
+}
+
+/// Maps scene coordinates (y up, origin at the centre) onto pixel coordinates (y down, origin top left).
+struct Transform {
+    scale: f32,
+    left: f32,
+    top: f32,
+}
+
+impl Transform {
+    fn apply(&self, p: Vec2) -> Vec2 {
+        Vec2::new(
+            (p.x - self.left) * self.scale,
+            (self.top - p.y) * self.scale,
+        )
+    }
+}
+
+fn draw_shape(image: &mut RgbaImage, transform: &Transform, shape: &Shape) {
+    match shape {
+        Shape::Polyline { points, stroke } => {
+            let points: Vec<Vec2> = points.iter().map(|&p| transform.apply(p)).collect();
+            draw_stroke(image, &points, false, stroke, transform.scale);
+        }
+        Shape::Polygon {
+            points,
+            fill,
+            stroke,
+        } => {
+            let points: Vec<Vec2> = points.iter().map(|&p| transform.apply(p)).collect();
+            if let Some(color) = fill {
+                fill_outlines(image, std::slice::from_ref(&points), *color);
+            }
+            if let Some(stroke) = stroke {
+                draw_stroke(image, &points, true, stroke, transform.scale);
+            }
+        }
+        Shape::Ellipse { xy, wh, fill } => {
+            let center = transform.apply(*xy);
+            let radii = *wh * 0.5 * transform.scale;
+            fill_outlines(image, &[ellipse_outline(center, radii)], *fill);
+        }
+    }
+}
+
+fn draw_stroke(image: &mut RgbaImage, points: &[Vec2], closed: bool, stroke: &Stroke, scale: f32) {
+    let half_weight = stroke.weight * scale * 0.5;
+    if points.len() < 2 || half_weight <= 0.0 {
+        return;
+    }
+    let mut segments: Vec<(Vec2, Vec2)> = points.windows(2).map(|w| (w[0], w[1])).collect();
+    if closed {
+        segments.push((points[points.len() - 1], points[0]));
+    }
+
+    let mut outlines = Vec::new();
+    for &(a, b) in &segments {
+        let direction = b - a;
+        let length = direction.length();
+        if length <= f32::EPSILON {
+            continue;
+        }
+        let normal = Vec2::new(-direction.y, direction.x) / length * half_weight;
+        outlines.push(vec![a + normal, b + normal, b - normal, a - normal]);
+    }
+    let joins = if closed {
+        points
+    } else {
+        &points[1..points.len() - 1]
+    };
+    let radii = Vec2::new(half_weight, half_weight);
+    outlines.extend(joins.iter().map(|&p| ellipse_outline(p, radii)));
+
+    fill_outlines(image, &outlines, stroke.color);
+}
+
+fn ellipse_outline(center: Vec2, radii: Vec2) -> Vec<Vec2> {
+    let circumference = std::f32::consts::TAU * radii.x.max(radii.y);
+    let resolution = (circumference / 2.0).ceil().max(12.0) as usize;
+    (0..resolution)
+        .map(|i| {
+            let angle = i as f32 / resolution as f32 * std::f32::consts::TAU;
+            center + Vec2::new(angle.cos() * radii.x, angle.sin() * radii.y)
+        })
+        .collect()
+}
+
+/// Fill the union of the given closed outlines with `color`.
+fn fill_outlines(image: &mut RgbaImage, outlines: &[Vec<Vec2>], color: Srgba<u8>) {
+    let points = outlines.iter().flatten();
+    let (min, max) = points.fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(min, max), &p| (min.min(p), max.max(p)),
+    );
+    let x0 = (min.x.floor().max(0.0) as u32).min(image.width());
+    let y0 = (min.y.floor().max(0.0) as u32).min(image.height());
+    let x1 = (max.x.ceil().max(0.0) as u32 + 1).min(image.width());
+    let y1 = (max.y.ceil().max(0.0) as u32 + 1).min(image.height());
+    if x1 <= x0 || y1 <= y0 {
+        return;
+    }
+
+    let mut coverage = Coverage::new(x0, y0, x1 - x0, y1 - y0);
+    for outline in outlines {
+        // Wind every outline the same way so that overlaps add up rather than cancel.
+        let reverse = signed_area(outline) < 0.0;
+        for i in 0..outline.len() {
+            let (a, b) = (outline[i], outline[(i + 1) % outline.len()]);
+            if reverse {
+                coverage.line(b, a);
+            } else {
+                coverage.line(a, b);
+            }
+        }
+    }
+    coverage.composite(image, color);
+}
+
+fn signed_area(outline: &[Vec2]) -> f32 {
+    (0..outline.len())
+        .map(|i| {
+            let (a, b) = (outline[i], outline[(i + 1) % outline.len()]);
+            a.x * b.y - b.x * a.y
+        })
+        .sum::<f32>()
+        * 0.5
+}
+
+/// Signed area accumulation buffer covering one shape's pixel bounds.
+struct Coverage {
+    x0: u32,
+    y0: u32,
+    w: usize,
+    h: usize,
+    // Each row has room for the spill past the right edge.
+    stride: usize,
+    acc: Vec<f32>,
+}
+
+impl Coverage {
+    fn new(x0: u32, y0: u32, w: u32, h: u32) -> Self {
+        let (w, h) = (w as usize, h as usize);
+        let stride = w + 2;
+        Coverage {
+            x0,
+            y0,
+            w,
+            h,
+            stride,
+            acc: vec![0.0; stride * h],
+        }
+    }
+
+    fn line(&mut self, p0: Vec2, p1: Vec2) {
+        let offset = Vec2::new(self.x0 as f32, self.y0 as f32);
+        let (p0, p1) = (p0 - offset, p1 - offset);
+
+        // Split at the left and right edges. Whatever lies outside is flattened onto the edge, which
+        // keeps its contribution to the winding of every pixel inside.
+        let w = self.w as f32;
+        let mut splits = vec![0.0, 1.0];
+        for edge in [0.0, w] {
+            if (p0.x - edge) * (p1.x - edge) < 0.0 {
+                splits.push((edge - p0.x) / (p1.x - p0.x));
+            }
+        }
+        splits.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for t in splits.windows(2) {
+            let a = p0.lerp(p1, t[0]);
+            let b = p0.lerp(p1, t[1]);
+            self.accumulate(
+                Vec2::new(a.x.clamp(0.0, w), a.y),
+                Vec2::new(b.x.clamp(0.0, w), b.y),
+            );
+        }
+    }
+
+    fn accumulate(&mut self, p0: Vec2, p1: Vec2) {
+        if (p0.y - p1.y).abs() <= f32::EPSILON {
+            return;
+        }
+        let (dir, p0, p1) = if p0.y < p1.y {
+            (1.0, p0, p1)
+        } else {
+            (-1.0, p1, p0)
+        };
+        let w = self.w as f32;
+        let dxdy = (p1.x - p0.x) / (p1.y - p0.y);
+        let mut x = p0.x;
+        if p0.y < 0.0 {
+            x -= p0.y * dxdy;
+        }
+        let y_start = p0.y.max(0.0) as usize;
+        let y_end = (self.h as f32).min(p1.y.ceil()).max(0.0) as usize;
+        for y in y_start..y_end {
+            let row = y * self.stride;
+            let dy = ((y + 1) as f32).min(p1.y) - (y as f32).max(p0.y);
+            let x_next = (x + dxdy * dy).clamp(0.0, w);
+            let d = dy * dir;
+            let (x0, x1) = if x < x_next { (x, x_next) } else { (x_next, x) };
+            let x0_floor = x0.floor();
+            let x0i = x0_floor as usize;
+            let x1_ceil = x1.ceil();
+            let x1i = x1_ceil as usize;
+            if x1i <= x0i + 1 {
+                // The edge stays within a single pixel column on this row.
+                let xmf = 0.5 * (x + x_next) - x0_floor;
+                self.acc[row + x0i] += d - d * xmf;
+                self.acc[row + x0i + 1] += d * xmf;
+            } else {
+                let s = (x1 - x0).recip();
+                let x0f = x0 - x0_floor;
+                let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+                let x1f = x1 - x1_ceil + 1.0;
+                let am = 0.5 * s * x1f * x1f;
+                self.acc[row + x0i] += d * a0;
+                if x1i == x0i + 2 {
+                    self.acc[row + x0i + 1] += d * (1.0 - a0 - am);
+                } else {
+                    let a1 = s * (1.5 - x0f);
+                    self.acc[row + x0i + 1] += d * (a1 - a0);
+                    for xi in x0i + 2..x1i - 1 {
+                        self.acc[row + xi] += d * s;
+                    }
+                    let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                    self.acc[row + x1i - 1] += d * (1.0 - a2 - am);
+                }
+                self.acc[row + x1i] += d * am;
+            }
+            x = x_next;
+        }
+    }
+
+    fn composite(&self, image: &mut RgbaImage, color: Srgba<u8>) {
+        let alpha = color.alpha as f32 / 255.0;
+        for y in 0..self.h {
+            let mut winding = 0.0;
+            for x in 0..self.w {
+                winding += self.acc[y * self.stride + x];
+                let coverage = winding.abs().min(1.0) * alpha;
+                if coverage < MIN_COVERAGE {
+                    continue;
+                }
+                let pixel = image.get_pixel_mut(self.x0 + x as u32, self.y0 + y as u32);
+                blend(pixel, color, coverage);
+            }
+        }
+    }
+}
+
+fn blend(pixel: &mut Rgba<u8>, color: Srgba<u8>, coverage: f32) {
+    let src = [color.red, color.green, color.blue];
+    for (dst, src) in pixel.0.iter_mut().zip(src.iter()) {
+        *dst = (*src as f32 * coverage + *dst as f32 * (1.0 - coverage)).round() as u8;
+    }
+    pixel.0[3] = (255.0 * coverage + pixel.0[3] as f32 * (1.0 - coverage)).round() as u8;
+}
+
+fn to_pixel(color: Srgba<u8>) -> Rgba<u8> {
+    Rgba([color.red, color.green, color.blue, color.alpha])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raster::render;
+    use crate::scene::Scene;
+    use nannou::color::{Srgb, Srgba};
+    use nannou::geom::{Rect, Vec2};
+
+    fn blank_scene() -> Scene {
+        let rect = Rect::from_w_h(100.0, 100.0);
+        Scene::new(rect, Srgb::new(255u8, 255, 255))
+    }
+
+    #[test]
+    fn solid_rect_covers_its_pixels() {
+        let mut scene = blank_scene();
+        let black = Srgba::new(0u8, 0, 0, 255);
+        scene.rect(&Rect::from_x_y_w_h(-25.0, 25.0, 50.0, 50.0), black);
+        let image = render(&scene, 100, 100);
+
+        // Top left quarter is filled, the rest is untouched.
+        assert_eq!(image.get_pixel(10, 10).0, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(49, 49).0, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(50, 50).0, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(75, 25).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn edges_are_anti_aliased() {
+        let mut scene = blank_scene();
+        let black = Srgba::new(0u8, 0, 0, 255);
+        scene.rect(&Rect::from_x_y_w_h(5.25, 0.0, 20.5, 20.0), black);
+        let image = render(&scene, 100, 100);
+
+        // The rect's right edge lands halfway through a pixel.
+        let edge = image.get_pixel(65, 50).0[0];
+        assert!(edge > 100 && edge < 155, "{}", edge);
+    }
+
+    #[test]
+    fn polylines_are_stroked() {
+        let mut scene = blank_scene();
+        let black = Srgba::new(0u8, 0, 0, 255);
+        let points = vec![Vec2::new(-40.0, 0.0), Vec2::new(40.0, 0.0)];
+        scene.polyline(points, black, 4.0);
+        let image = render(&scene, 100, 100);
+
+        assert_eq!(image.get_pixel(50, 49).0, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(50, 40).0, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(5, 50).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn shapes_outside_the_canvas_are_clipped() {
+        let mut scene = blank_scene();
+        let black = Srgba::new(0u8, 0, 0, 255);
+        scene.rect(&Rect::from_x_y_w_h(0.0, 0.0, 400.0, 400.0), black);
+        scene.ellipse(Vec2::new(500.0, 0.0), 10.0, black);
+        let image = render(&scene, 100, 100);
+
+        assert!(image.pixels().all(|p| p.0 == [0, 0, 0, 255]));
+    }
+}
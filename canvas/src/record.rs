@@ -0,0 +1,609 @@
+//! Frame recording shared by the sketches, configured at runtime.
+//!
+//! - `--record` turns recording on for sketches that do not record by default. Given a `.gif`,
+//!   `.png` or `.apng` path, the frames go into one animation instead, see `animation`.
+//! - `--frames 100..200` picks the frames to record, counting from 0 with the end left out.
+//!   `100..=199` includes the end, `100..` never stops and `7` is frame 7 alone.
+//! - `--every 10` keeps every 10th frame of that range.
+//! - `--frames-dir out` writes the numbered frames to `out` instead of the sketch's `frames`.
+//! - `--frame-name '{sketch}-{seed}-{frame:05}.png'` names them, from the sketch's name, the seed
+//!   and the frame number, optionally zero padded. The default is `{frame:05}.png`.
+//! - `--resume` keeps the whole frames an interrupted run left in the directory and starts again
+//!   from the first one missing. Run `verify-frames` on a directory to list its gaps.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use image::RgbaImage;
+
+use crate::animation::Recorder;
+use crate::args::Args;
+use crate::encode::{EncodePool, EncodeSettings, Frame, Slot};
+use crate::metadata::{self, Metadata};
+use crate::scene::Scene;
+use crate::seed;
+use crate::verify;
+
+pub const DEFAULT_FRAME_NAME: &str = "{frame:05}.png";
+/// The completion log in the frames directory, one `frame<TAB>path` line per frame written.
+pub const LOG_NAME: &str = "frames.log";
+
+/// The frames to record: from `start` up to but not including `end`, keeping every `every`th.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameRange {
+    pub start: u64,
+    pub end: Option<u64>,
+    pub every: u64,
+}
+
+impl FrameRange {
+    /// The first `count` frames.
+    pub fn first(count: u64) -> Self {
+        FrameRange {
+            start: 0,
+            end: Some(count),
+            every: 1,
+        }
+    }
+
+    /// Every frame from the first on.
+    pub fn all() -> Self {
+        FrameRange {
+            start: 0,
+            end: None,
+            every: 1,
+        }
+    }
+
+    pub fn contains(&self, nth: u64) -> bool {
+        self.index(nth).is_some()
+    }
+
+    /// Where frame `nth` falls among the recorded frames, if it is recorded at all.
+    pub fn index(&self, nth: u64) -> Option<u64> {
+        let offset = nth.checked_sub(self.start)?;
+        if self.end.is_some_and(|end| nth >= end) || offset % self.every.max(1) != 0 {
+            return None;
+        }
+        Some(offset / self.every.max(1))
+    }
+}
+
+impl fmt::Display for FrameRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..", self.start)?;
+        if let Some(end) = self.end {
+            write!(f, "{}", end)?;
+        }
+        if self.every > 1 {
+            write!(f, " every {}", self.every)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FrameRangeParseErr {
+    BadRangeErr(String),
+}
+
+impl fmt::Display for FrameRangeParseErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameRangeParseErr::BadRangeErr(s) => write!(
+                f,
+                "invalid frame range {:?}, expected START..END, START..=END, START.. or a frame number",
+                s
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FrameRangeParseErr {}
+
+impl FromStr for FrameRange {
+    type Err = FrameRangeParseErr;
+
+    /// Parse `START..END`, `START..=END`, `START..`, `..END` or a single frame number. The stride is
+    /// left at 1.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || FrameRangeParseErr::BadRangeErr(s.to_string());
+        let number = |n: &str| n.trim().parse::<u64>().map_err(|_| bad());
+        let (start, end) = match s.split_once("..") {
+            None => {
+                let frame = number(s)?;
+                (frame, Some(frame + 1))
+            }
+            Some((start, end)) => {
+                let start = if start.trim().is_empty() {
+                    0
+                } else {
+                    number(start)?
+                };
+                let end = match end.strip_prefix('=') {
+                    Some(end) => Some(number(end)? + 1),
+                    None if end.trim().is_empty() => None,
+                    None => Some(number(end)?),
+                };
+                (start, end)
+            }
+        };
+        if end.is_some_and(|end| end <= start) {
+            return Err(bad());
+        }
+        Ok(FrameRange {
+            start,
+            end,
+            every: 1,
+        })
+    }
+}
+
+/// A file name with `{sketch}`, `{seed}` and `{frame}` tokens. `{frame:05}` pads the frame number
+/// with zeros to five digits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NameTemplate(Vec<Part>);
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Sketch,
+    Seed,
+    /// The frame number, padded with zeros to the width.
+    Frame(usize),
+}
+
+impl NameTemplate {
+    pub fn render(&self, sketch: &str, seed: u64, frame: u64) -> String {
+        self.0
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Sketch => sketch.to_string(),
+                Part::Seed => seed.to_string(),
+                Part::Frame(width) => format!("{:0width$}", frame, width = width),
+            })
+            .collect()
+    }
+
+    pub fn uses_seed(&self) -> bool {
+        self.0.contains(&Part::Seed)
+    }
+
+    /// The frame number in a file `name` made from this template, if it was. Without a `sketch` or
+    /// `seed` to look for, any sketch name or seed matches.
+    pub fn frame_number(&self, name: &str, sketch: Option<&str>, seed: Option<u64>) -> Option<u64> {
+        match_parts(&self.0, name, sketch, seed, None)
+    }
+}
+
+/// Match `name` against `parts`, trying every split where a token could end.
+fn match_parts(
+    parts: &[Part],
+    name: &str,
+    sketch: Option<&str>,
+    seed: Option<u64>,
+    frame: Option<u64>,
+) -> Option<u64> {
+    let (part, rest) = match parts.split_first() {
+        Some(split) => split,
+        None => return if name.is_empty() { frame } else { None },
+    };
+    let next = |name: &str, frame| match_parts(rest, name, sketch, seed, frame);
+    let digits = name.bytes().take_while(u8::is_ascii_digit).count();
+    match part {
+        Part::Text(text) => next(name.strip_prefix(text.as_str())?, frame),
+        Part::Sketch => match sketch {
+            Some(sketch) => next(name.strip_prefix(sketch)?, frame),
+            None => (1..=name.len())
+                .filter(|&end| name.is_char_boundary(end))
+                .find_map(|end| next(&name[end..], frame)),
+        },
+        Part::Seed => match seed {
+            Some(seed) => next(name.strip_prefix(&seed.to_string())?, frame),
+            None => (1..=digits).rev().find_map(|end| next(&name[end..], frame)),
+        },
+        Part::Frame(width) => (1..=digits)
+            .rev()
+            .filter(|&end| end >= *width)
+            .find_map(|end| next(&name[end..], Some(name[..end].parse().ok()?))),
+    }
+}
+
+impl Default for NameTemplate {
+    fn default() -> Self {
+        DEFAULT_FRAME_NAME
+            .parse()
+            .expect("the default frame name is valid")
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum NameTemplateParseErr {
+    UnknownTokenErr(String),
+    UnclosedTokenErr,
+    /// Without the frame number every frame would be written to the same file.
+    MissingFrameErr,
+}
+
+impl fmt::Display for NameTemplateParseErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NameTemplateParseErr::UnknownTokenErr(token) => write!(
+                f,
+                "unknown token {{{}}}, expected {{sketch}}, {{seed}}, {{frame}} or {{frame:05}}",
+                token
+            ),
+            NameTemplateParseErr::UnclosedTokenErr => write!(f, "a {{ is never closed"),
+            NameTemplateParseErr::MissingFrameErr => {
+                write!(f, "the name needs a {{frame}} token to tell frames apart")
+            }
+        }
+    }
+}
+
+impl std::error::Error for NameTemplateParseErr {}
+
+impl FromStr for NameTemplate {
+    type Err = NameTemplateParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..]
+                .find('}')
+                .map(|close| open + close)
+                .ok_or(NameTemplateParseErr::UnclosedTokenErr)?;
+            if open > 0 {
+                parts.push(Part::Text(rest[..open].to_string()));
+            }
+            let token = &rest[open + 1..close];
+            let width = token
+                .strip_prefix("frame:0")
+                .and_then(|width| width.parse::<usize>().ok());
+            parts.push(match (token, width) {
+                ("sketch", _) => Part::Sketch,
+                ("seed", _) => Part::Seed,
+                ("frame", _) => Part::Frame(0),
+                (_, Some(width)) => Part::Frame(width),
+                _ => return Err(NameTemplateParseErr::UnknownTokenErr(token.to_string())),
+            });
+            rest = &rest[close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        if !parts.iter().any(|part| matches!(part, Part::Frame(_))) {
+            return Err(NameTemplateParseErr::MissingFrameErr);
+        }
+        Ok(NameTemplate(parts))
+    }
+}
+
+/// Records a sketch's frames, either as numbered PNGs or into one animation. Cloning gives another
+/// handle to the same recording, so frames can be handed over from capture threads.
+///
+/// Numbered frames are written by an `EncodePool`. Take a slot with `reserve` before capturing a
+/// frame, which waits while the pool is full, and hand it back with the frame to `add_frame`.
+#[derive(Clone)]
+pub struct Recording {
+    frames: FrameRange,
+    dir: PathBuf,
+    template: NameTemplate,
+    metadata: Metadata,
+    sketch: String,
+    seed: u64,
+    animation: Option<Recorder>,
+    pool: Arc<EncodePool>,
+    /// Frames a resumed recording already has.
+    done: Arc<BTreeSet<u64>>,
+    fast_forward: u64,
+}
+
+impl Recording {
+    /// The recording asked for on the command line. Sketches that record `frames` by default pass
+    /// them, and the others only record with `--record` or `--frames`. Numbered frames go to `dir`
+    /// unless `--frames-dir` says otherwise, are stamped with `metadata`, whose sketch name also
+    /// fills in `{sketch}`, along with their number and size, and are listed in its `frames.log` as
+    /// they are written. `palette` is used for GIFs. Exits if the recording cannot be set up.
+    pub fn from_args(
+        args: &Args,
+        dir: PathBuf,
+        frames: Option<FrameRange>,
+        metadata: Metadata,
+        palette: Vec<[u8; 3]>,
+    ) -> Option<Self> {
+        let asked = ["record", "frames", "resume"]
+            .iter()
+            .any(|name| args.flag(name));
+        if frames.is_none() && !asked {
+            return None;
+        }
+        let mut frames = args
+            .parsed::<FrameRange>("frames")
+            .or(frames)
+            .unwrap_or_else(FrameRange::all);
+        frames.every = args.parsed("every").unwrap_or(frames.every).max(1);
+        let dir = args.value("frames-dir").map(PathBuf::from).unwrap_or(dir);
+        let animation = Recorder::from_args(args, palette);
+        // Animations are encoded in order as frames arrive, so only numbered frames are logged.
+        let log = animation.is_none().then(|| dir.join(LOG_NAME));
+        let pool = match EncodePool::new(&EncodeSettings::from_args(args), log.as_deref()) {
+            Ok(pool) => pool,
+            Err(err) => {
+                eprintln!("Cannot record to {}: {}", dir.display(), err);
+                std::process::exit(1);
+            }
+        };
+        let template: NameTemplate = args.parsed("frame-name").unwrap_or_default();
+        let sketch = metadata
+            .get(metadata::SKETCH)
+            .unwrap_or("sketch")
+            .to_string();
+        let seed = seed::master();
+        let (done, fast_forward) = if args.flag("resume") {
+            if animation.is_some() {
+                eprintln!("Only numbered frames can be resumed, not an animation");
+                std::process::exit(2);
+            }
+            resume(&dir, &template, &sketch, seed, &frames)
+        } else {
+            (BTreeSet::new(), 0)
+        };
+        Some(Recording {
+            frames,
+            dir,
+            template,
+            sketch,
+            seed,
+            metadata,
+            animation,
+            pool: Arc::new(pool),
+            done: Arc::new(done),
+            fast_forward,
+        })
+    }
+
+    pub fn frames(&self) -> FrameRange {
+        self.frames
+    }
+
+    /// Whether frame `nth` is one to record and not already recorded.
+    pub fn records(&self, nth: u64) -> bool {
+        self.frames.contains(nth) && !self.done.contains(&nth)
+    }
+
+    /// The frame a resumed recording starts from, its first missing one, or 0. A sketch skips ahead
+    /// to it by adding it to its own frame count, after stepping its simulation that far.
+    pub fn fast_forward(&self) -> u64 {
+        self.fast_forward
+    }
+
+    /// Where frame `nth` is written when recording numbered frames.
+    pub fn frame_path(&self, nth: u64) -> PathBuf {
+        self.dir
+            .join(self.template.render(&self.sketch, self.seed, nth))
+    }
+
+    /// Take a place for the next frame, waiting while as many frames as the pool allows are still
+    /// being written.
+    pub fn reserve(&self) -> Slot {
+        self.pool.reserve()
+    }
+
+    /// Like `reserve`, calling `poll` while it waits, see `EncodePool::reserve_polling`.
+    pub fn reserve_polling(&self, poll: impl FnMut()) -> Slot {
+        self.pool.reserve_polling(poll)
+    }
+
+    /// Record frame `nth`, if it is in the range, from an image of it.
+    pub fn add_frame(&self, slot: Slot, nth: u64, image: RgbaImage) -> io::Result<()> {
+        self.add(slot, nth, Frame::Image(image))
+    }
+
+    /// Record frame `nth`, if it is in the range, by rendering `scene` on the CPU at one pixel per
+    /// point. Numbered frames are rendered by the pool's threads.
+    pub fn add_scene(&self, nth: u64, scene: Scene) -> io::Result<()> {
+        let (width, height) = (scene.rect.w() as u32, scene.rect.h() as u32);
+        self.add_scene_at(nth, scene, width, height)
+    }
+
+    /// Like `add_scene`, but rendering `scene` to fill `width` by `height` pixels.
+    pub fn add_scene_at(&self, nth: u64, scene: Scene, width: u32, height: u32) -> io::Result<()> {
+        if !self.records(nth) {
+            return Ok(());
+        }
+        let slot = self.reserve();
+        self.add(
+            slot,
+            nth,
+            Frame::Scene {
+                scene,
+                width,
+                height,
+            },
+        )
+    }
+
+    fn add(&self, slot: Slot, nth: u64, frame: Frame) -> io::Result<()> {
+        let index = match self.frames.index(nth) {
+            Some(index) if !self.done.contains(&nth) => index,
+            _ => return Ok(()),
+        };
+        if let Some(animation) = &self.animation {
+            return animation.add_frame(index, frame.into_image());
+        }
+        let path = self.frame_path(nth);
+        let (width, height) = frame.size();
+        let metadata = self
+            .metadata
+            .clone()
+            .with(metadata::FRAME, nth)
+            .with(metadata::SIZE, format!("{}x{}", width, height));
+        self.pool.submit(slot, nth, path, frame, metadata);
+        Ok(())
+    }
+
+    /// Finish the animation, or wait for the numbered frames to be written, and report what was
+    /// recorded.
+    pub fn finish(&self) -> io::Result<()> {
+        let written = self.pool.finish()?;
+        if let Some(animation) = &self.animation {
+            let frames = animation.finish(&self.metadata)?;
+            println!("Saved {} ({} frames)", animation.path().display(), frames);
+            return Ok(());
+        }
+        println!("Saved {} frames to {}", written, self.dir.display());
+        Ok(())
+    }
+}
+
+/// The frames of `frames` already in `dir` from an earlier run with the same seed, and the first
+/// one missing. Exits if the frames there were recorded with another seed.
+fn resume(
+    dir: &Path,
+    template: &NameTemplate,
+    sketch: &str,
+    seed: u64,
+    frames: &FrameRange,
+) -> (BTreeSet<u64>, u64) {
+    let report = match verify::scan(dir, template, Some(sketch), Some(seed)) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Cannot resume from {}: {}", dir.display(), err);
+            std::process::exit(1);
+        }
+    };
+    // Names without the seed cannot tell runs apart, so the frames have to say which seed they are.
+    if let Some(&first) = report.valid.first().filter(|_| !template.uses_seed()) {
+        let path = dir.join(template.render(sketch, seed, first));
+        let recorded = Metadata::read(&path).ok();
+        if let Some(recorded) = recorded.as_ref().and_then(|m| m.get(metadata::SEED)) {
+            if recorded != seed.to_string() {
+                eprintln!(
+                    "The frames in {} were recorded with seed {}, pass --seed {} to resume them",
+                    dir.display(),
+                    recorded,
+                    recorded
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+    let first_missing = report.first_missing(frames);
+    let done: BTreeSet<u64> = report
+        .valid
+        .into_iter()
+        .filter(|&nth| frames.contains(nth))
+        .collect();
+    println!(
+        "Resuming at frame {}, {} frames already recorded",
+        first_missing,
+        done.len()
+    );
+    (done, first_missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::Args;
+    use crate::metadata::{Metadata, FRAME, SEED, SIZE};
+    use crate::record::{FrameRange, NameTemplate, NameTemplateParseErr, Recording};
+    use crate::scene::Scene;
+    use crate::testing::TempDir;
+    use nannou::color::Srgb;
+    use nannou::geom::Rect;
+
+    #[test]
+    fn parse_ranges() {
+        let range: FrameRange = "100..200".parse().unwrap();
+        assert_eq!((range.start, range.end), (100, Some(200)));
+        assert_eq!("100..=199".parse(), Ok(range));
+        assert_eq!("5..".parse::<FrameRange>().unwrap().end, None);
+        assert_eq!("..3".parse(), Ok(FrameRange::first(3)));
+        assert_eq!("7".parse::<FrameRange>().unwrap().end, Some(8));
+        assert!("9..3".parse::<FrameRange>().is_err());
+        assert!("a..b".parse::<FrameRange>().is_err());
+    }
+
+    #[test]
+    fn strides_number_the_kept_frames() {
+        let range = FrameRange {
+            start: 10,
+            end: Some(40),
+            every: 10,
+        };
+        let kept: Vec<(u64, u64)> = (0..50)
+            .filter_map(|nth| range.index(nth).map(|index| (nth, index)))
+            .collect();
+        assert_eq!(kept, vec![(10, 0), (20, 1), (30, 2)]);
+    }
+
+    #[test]
+    fn templates_fill_in_their_tokens() {
+        let template: NameTemplate = "{sketch}-{seed}-{frame:05}.png".parse().unwrap();
+        assert_eq!(template.render("mesh", 42, 7), "mesh-42-00007.png");
+        assert_eq!(NameTemplate::default().render("mesh", 42, 123), "00123.png");
+        let unpadded: NameTemplate = "{frame}.png".parse().unwrap();
+        assert_eq!(unpadded.render("cells", 1, 3), "3.png");
+
+        assert_eq!(
+            "{sketch}.png".parse::<NameTemplate>(),
+            Err(NameTemplateParseErr::MissingFrameErr)
+        );
+        assert_eq!(
+            "{frame}-{date}.png".parse::<NameTemplate>(),
+            Err(NameTemplateParseErr::UnknownTokenErr("date".to_string()))
+        );
+        assert_eq!(
+            "{frame".parse::<NameTemplate>(),
+            Err(NameTemplateParseErr::UnclosedTokenErr)
+        );
+    }
+
+    #[test]
+    fn templates_read_frame_numbers_back() {
+        let template: NameTemplate = "{sketch}-{seed}-{frame:05}.png".parse().unwrap();
+        let name = template.render("ten-thousand", 42, 123);
+        assert_eq!(template.frame_number(&name, None, None), Some(123));
+        assert_eq!(
+            template.frame_number(&name, Some("ten-thousand"), Some(42)),
+            Some(123)
+        );
+        assert_eq!(template.frame_number(&name, None, Some(43)), None);
+        assert_eq!(
+            template.frame_number("mesh-42-00123.png.partial", None, None),
+            None
+        );
+        // Padded names need at least the padding, past it any number of digits.
+        assert_eq!(template.frame_number("mesh-42-123.png", None, None), None);
+        assert_eq!(
+            template.frame_number("mesh-42-123456.png", None, None),
+            Some(123456)
+        );
+    }
+
+    #[test]
+    fn frames_are_stamped_with_their_number_and_size() {
+        let dir = TempDir::new("record");
+        let args = Args::parse(["--frames", "2"].map(String::from));
+        let metadata = Metadata::default().with(SEED, 5);
+        let recording =
+            Recording::from_args(&args, dir.join("frames"), None, metadata, Vec::new()).unwrap();
+        for nth in 0..3 {
+            let scene = Scene::new(Rect::from_w_h(8.0, 6.0), Srgb::new(255u8, 255, 255));
+            recording.add_scene_at(nth, scene, 16, 12).unwrap();
+        }
+        recording.finish().unwrap();
+
+        let stamped = Metadata::read(&recording.frame_path(2)).unwrap();
+        assert_eq!(stamped.get(SEED), Some("5"));
+        assert_eq!(stamped.get(FRAME), Some("2"));
+        assert_eq!(stamped.get(SIZE), Some("16x12"));
+    }
+}
@@ -0,0 +1,158 @@
+use nannou::color::{Srgb, Srgba};
+use nannou::geom::{Rect, Vec2};
+use nannou::Draw;
+
+/// Everything a sketch draws for one frame, independent of the backend that renders it.
+///
+/// Coordinates follow nannou: the origin is the centre of `rect` and y points up.
+pub struct Scene {
+    pub rect: Rect,
+    pub units: Units,
+    pub background: Srgba<u8>,
+    pub shapes: Vec<Shape>,
+}
+
+/// What a scene's coordinates measure.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Units {
+    /// Pixels, or any other unit with no size on paper. Such scenes are scaled to fit the paper.
+    Pixels,
+    /// Millimetres on the paper, kept at their true size by the plot and vector outputs.
+    Mm,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stroke {
+    pub color: Srgba<u8>,
+    pub weight: f32,
+    /// Printed on every plate whatever its colour, as crop and registration marks are.
+    pub registration: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Polyline {
+        points: Vec<Vec2>,
+        stroke: Stroke,
+    },
+    Polygon {
+        points: Vec<Vec2>,
+        fill: Option<Srgba<u8>>,
+        stroke: Option<Stroke>,
+    },
+    Ellipse {
+        xy: Vec2,
+        wh: Vec2,
+        fill: Srgba<u8>,
+    },
+}
+
+impl Scene {
+    pub fn new(rect: Rect, background: Srgb<u8>) -> Self {
+        Scene {
+            rect,
+            units: Units::Pixels,
+            background: background.into(),
+            shapes: Vec::new(),
+        }
+    }
+
+    /// Mark the scene as laid out in millimetres on the paper, see `Units::Mm`.
+    pub fn in_mm(mut self) -> Self {
+        self.units = Units::Mm;
+        self
+    }
+
+    pub fn polyline<I, C>(&mut self, points: I, color: C, weight: f32)
+    where
+        I: IntoIterator,
+        I::Item: Into<Vec2>,
+        C: Into<Srgba<u8>>,
+    {
+        self.shapes.push(Shape::Polyline {
+            points: points.into_iter().map(Into::into).collect(),
+            stroke: Stroke {
+                color: color.into(),
+                weight,
+                registration: false,
+            },
+        });
+    }
+
+    pub fn polygon<I, C>(&mut self, points: I, color: C)
+    where
+        I: IntoIterator,
+        I::Item: Into<Vec2>,
+        C: Into<Srgba<u8>>,
+    {
+        self.shapes.push(Shape::Polygon {
+            points: points.into_iter().map(Into::into).collect(),
+            fill: Some(color.into()),
+            stroke: None,
+        });
+    }
+
+    pub fn rect<C: Into<Srgba<u8>>>(&mut self, rect: &Rect, color: C) {
+        self.polygon(rect_points(rect), color);
+    }
+
+    pub fn rect_outline<C: Into<Srgba<u8>>>(&mut self, rect: &Rect, color: C, weight: f32) {
+        self.shapes.push(Shape::Polygon {
+            points: rect_points(rect),
+            fill: None,
+            stroke: Some(Stroke {
+                color: color.into(),
+                weight,
+                registration: false,
+            }),
+        });
+    }
+
+    pub fn ellipse<C: Into<Srgba<u8>>>(&mut self, xy: Vec2, radius: f32, color: C) {
+        self.shapes.push(Shape::Ellipse {
+            xy,
+            wh: Vec2::new(radius * 2.0, radius * 2.0),
+            fill: color.into(),
+        });
+    }
+
+    /// Replay the scene onto a nannou `Draw` so the window shows exactly what the other backends render.
+    pub fn draw(&self, draw: &Draw) {
+        draw.background().color(self.background);
+        for shape in &self.shapes {
+            match shape {
+                Shape::Polyline { points, stroke } => {
+                    draw.polyline()
+                        .color(stroke.color)
+                        .weight(stroke.weight)
+                        .points(points.clone());
+                }
+                Shape::Polygon {
+                    points,
+                    fill,
+                    stroke,
+                } => {
+                    let polygon = draw.polygon();
+                    let polygon = match fill {
+                        Some(color) => polygon.color(*color),
+                        None => polygon.no_fill(),
+                    };
+                    let polygon = match stroke {
+                        Some(stroke) => polygon
+                            .stroke_color(stroke.color)
+                            .stroke_weight(stroke.weight),
+                        None => polygon,
+                    };
+                    polygon.points(points.clone());
+                }
+                Shape::Ellipse { xy, wh, fill } => {
+                    draw.ellipse().xy(*xy).wh(*wh).color(*fill);
+                }
+            }
+        }
+    }
+}
+
+pub fn rect_points(rect: &Rect) -> Vec<Vec2> {
+    rect.corners_iter().map(|[x, y]| Vec2::new(x, y)).collect()
+}
@@ -0,0 +1,52 @@
+//! The master seed that every random choice in a sketch is derived from.
+//!
+//! Pass `--seed 1234` to reproduce an earlier output. Without it a fresh seed is drawn, and either
+//! way the seed in use is printed so that a good result can be recreated later.
+
+use std::sync::OnceLock;
+
+use nannou::rand::{random, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::args::Args;
+
+static MASTER: OnceLock<u64> = OnceLock::new();
+
+/// The seed for this run, read from `--seed` or drawn at random on first use and printed.
+pub fn master() -> u64 {
+    *MASTER.get_or_init(|| {
+        let seed = Args::from_env().parsed("seed").unwrap_or_else(random);
+        println!("Seed: {}", seed);
+        seed
+    })
+}
+
+/// A generator seeded from `seed`. The same seed always yields the same sequence, on every platform
+/// and in every release, which `StdRng` does not promise.
+pub fn rng(seed: u64) -> ChaCha8Rng {
+    ChaCha8Rng::seed_from_u64(seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::seed::rng;
+    use nannou::rand::Rng;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let a: Vec<u32> = rng(7)
+            .sample_iter(nannou::rand::distributions::Standard)
+            .take(8)
+            .collect();
+        let b: Vec<u32> = rng(7)
+            .sample_iter(nannou::rand::distributions::Standard)
+            .take(8)
+            .collect();
+        let c: Vec<u32> = rng(8)
+            .sample_iter(nannou::rand::distributions::Standard)
+            .take(8)
+            .collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+}
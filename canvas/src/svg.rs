@@ -0,0 +1,342 @@
+//! Vector export of a `Scene` as a standalone SVG document.
+//!
+//! The view box spans the scene rect in scene units, with the y axis flipped so the SVG looks the
+//! same as the window.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use nannou::color::Srgba;
+use nannou::geom::Vec2;
+
+use crate::scene::{Scene, Shape, Stroke, Units};
+
+pub fn render(scene: &Scene) -> String {
+    let mut svg = header(scene);
+    for shape in &scene.shapes {
+        writeln!(svg, "{}", element(scene, shape)).unwrap();
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Render the scene with its shapes grouped into Inkscape layers, one per colour, so each pen of a
+/// multi-pen plot can be drawn in turn. Layers are named after the colour's hex value and ordered
+/// from light to dark, the order they are usually plotted in. A shape that is both filled and
+/// stroked is split between the layers of its two colours.
+pub fn render_layers(scene: &Scene) -> String {
+    let mut layers: Vec<(Srgba<u8>, Vec<String>)> = Vec::new();
+    for shape in &scene.shapes {
+        for (color, element) in pen_elements(scene, shape) {
+            match layers.iter_mut().find(|(c, _)| hex(*c) == hex(color)) {
+                Some((_, elements)) => elements.push(element),
+                None => layers.push((color, vec![element])),
+            }
+        }
+    }
+    layers.sort_by(|a, b| luminance(b.0).partial_cmp(&luminance(a.0)).unwrap());
+
+    let mut svg = header(scene);
+    for (i, (color, elements)) in layers.iter().enumerate() {
+        writeln!(
+            svg,
+            r#"<g inkscape:groupmode="layer" inkscape:label="{}" id="layer{}">"#,
+            hex(*color),
+            i + 1
+        )
+        .unwrap();
+        for element in elements {
+            writeln!(svg, "{}", element).unwrap();
+        }
+        svg.push_str("</g>\n");
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+pub fn save(scene: &Scene, path: &Path) -> std::io::Result<()> {
+    write(path, render(scene))
+}
+
+pub fn save_layers(scene: &Scene, path: &Path) -> std::io::Result<()> {
+    write(path, render_layers(scene))
+}
+
+fn write(path: &Path, svg: String) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, svg)
+}
+
+/// The document up to and including the background, which is left out of any layer. A scene in
+/// millimetres gets its width and height in `mm` so it opens at its size on paper, while the view
+/// box keeps one user unit to a scene unit either way.
+fn header(scene: &Scene) -> String {
+    let (w, h) = (scene.rect.w(), scene.rect.h());
+    let unit = match scene.units {
+        Units::Mm => "mm",
+        Units::Pixels => "",
+    };
+    let mut svg = String::new();
+    writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="{w}{unit}" height="{h}{unit}" viewBox="0 0 {w} {h}">"#,
+        w = w,
+        h = h,
+        unit = unit
+    )
+    .unwrap();
+    writeln!(
+        svg,
+        r#"<rect width="{}" height="{}"{}/>"#,
+        w,
+        h,
+        fill_attributes(scene.background)
+    )
+    .unwrap();
+    svg
+}
+
+/// The shape as elements drawn with a single colour each, paired with that colour.
+fn pen_elements(scene: &Scene, shape: &Shape) -> Vec<(Srgba<u8>, String)> {
+    match shape {
+        Shape::Polyline { stroke, .. } => vec![(stroke.color, element(scene, shape))],
+        Shape::Polygon {
+            points,
+            fill: Some(fill),
+            stroke: Some(stroke),
+        } => vec![
+            (
+                *fill,
+                element(
+                    scene,
+                    &Shape::Polygon {
+                        points: points.clone(),
+                        fill: Some(*fill),
+                        stroke: None,
+                    },
+                ),
+            ),
+            (
+                stroke.color,
+                element(
+                    scene,
+                    &Shape::Polygon {
+                        points: points.clone(),
+                        fill: None,
+                        stroke: Some(*stroke),
+                    },
+                ),
+            ),
+        ],
+        Shape::Polygon {
+            fill: Some(color), ..
+        }
+        | Shape::Polygon {
+            stroke: Some(Stroke { color, .. }),
+            ..
+        } => vec![(*color, element(scene, shape))],
+        Shape::Polygon { .. } => Vec::new(),
+        Shape::Ellipse { fill, .. } => vec![(*fill, element(scene, shape))],
+    }
+}
+
+/// Relative luminance, from 0 for black to 1 for white.
+fn luminance(color: Srgba<u8>) -> f32 {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(color.red) + 0.7152 * linear(color.green) + 0.0722 * linear(color.blue)
+}
+
+fn element(scene: &Scene, shape: &Shape) -> String {
+    match shape {
+        Shape::Polyline { points, stroke } => format!(
+            r#"<polyline points="{}" fill="none"{}/>"#,
+            points_attribute(scene, points),
+            stroke_attributes(stroke)
+        ),
+        Shape::Polygon {
+            points,
+            fill,
+            stroke,
+        } => format!(
+            r#"<polygon points="{}"{}{}/>"#,
+            points_attribute(scene, points),
+            fill.map(fill_attributes)
+                .unwrap_or_else(|| r#" fill="none""#.to_string()),
+            stroke.as_ref().map(stroke_attributes).unwrap_or_default()
+        ),
+        Shape::Ellipse { xy, wh, fill } => {
+            let center = to_svg(scene, *xy);
+            if wh.x == wh.y {
+                format!(
+                    r#"<circle cx="{}" cy="{}" r="{}"{}/>"#,
+                    center.x,
+                    center.y,
+                    wh.x / 2.0,
+                    fill_attributes(*fill)
+                )
+            } else {
+                format!(
+                    r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}"{}/>"#,
+                    center.x,
+                    center.y,
+                    wh.x / 2.0,
+                    wh.y / 2.0,
+                    fill_attributes(*fill)
+                )
+            }
+        }
+    }
+}
+
+fn to_svg(scene: &Scene, p: Vec2) -> Vec2 {
+    Vec2::new(p.x - scene.rect.left(), scene.rect.top() - p.y)
+}
+
+fn points_attribute(scene: &Scene, points: &[Vec2]) -> String {
+    points
+        .iter()
+        .map(|&p| {
+            let p = to_svg(scene, p);
+            format!("{:.2},{:.2}", p.x, p.y)
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn fill_attributes(color: Srgba<u8>) -> String {
+    let mut attributes = format!(r#" fill="{}""#, hex(color));
+    if color.alpha < 255 {
+        write!(
+            attributes,
+            r#" fill-opacity="{:.3}""#,
+            color.alpha as f32 / 255.0
+        )
+        .unwrap();
+    }
+    attributes
+}
+
+fn stroke_attributes(stroke: &Stroke) -> String {
+    let mut attributes = format!(
+        r#" stroke="{}" stroke-width="{}" stroke-linejoin="round""#,
+        hex(stroke.color),
+        stroke.weight
+    );
+    if stroke.color.alpha < 255 {
+        write!(
+            attributes,
+            r#" stroke-opacity="{:.3}""#,
+            stroke.color.alpha as f32 / 255.0
+        )
+        .unwrap();
+    }
+    attributes
+}
+
+pub fn hex(color: Srgba<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scene::Scene;
+    use crate::svg::{render, render_layers};
+    use nannou::color::{Srgb, Srgba};
+    use nannou::geom::{Rect, Vec2};
+
+    #[test]
+    fn shapes_become_svg_elements() {
+        let mut scene = Scene::new(Rect::from_w_h(100.0, 50.0), Srgb::new(255u8, 255, 255));
+        let teal = Srgba::new(0x45u8, 0x86, 0x8F, 255);
+        scene.rect(&Rect::from_x_y_w_h(-40.0, 15.0, 10.0, 10.0), teal);
+        scene.polyline(
+            vec![Vec2::new(0.0, 0.0), Vec2::new(10.0, 10.0)],
+            Srgba::new(0u8, 0, 0, 128),
+            1.5,
+        );
+        scene.ellipse(Vec2::new(50.0, -25.0), 2.0, teal);
+        let svg = render(&scene);
+
+        assert!(
+            svg.contains(r#"width="100" height="50" viewBox="0 0 100 50""#),
+            "{}",
+            svg
+        );
+        // y is flipped: the rect's top edge at y = 20 sits 5 units below the top of the view box.
+        assert!(
+            svg.contains(r##"<polygon points="5.00,5.00 15.00,5.00 15.00,15.00 5.00,15.00" fill="#45868f"/>"##),
+            "{}",
+            svg
+        );
+        assert!(svg.contains(r##"<polyline points="50.00,25.00 60.00,15.00" fill="none" stroke="#000000" stroke-width="1.5""##), "{}", svg);
+        assert!(svg.contains(r#"stroke-opacity="0.502""#), "{}", svg);
+        assert!(
+            svg.contains(r##"<circle cx="100" cy="50" r="2" fill="#45868f"/>"##),
+            "{}",
+            svg
+        );
+    }
+
+    #[test]
+    fn millimetre_scenes_are_sized_in_millimetres() {
+        let scene = Scene::new(Rect::from_w_h(210.0, 297.0), Srgb::new(255u8, 255, 255)).in_mm();
+        let svg = render(&scene);
+        assert!(
+            svg.contains(r#"width="210mm" height="297mm" viewBox="0 0 210 297""#),
+            "{}",
+            svg
+        );
+    }
+
+    #[test]
+    fn layers_group_by_colour_from_light_to_dark() {
+        let mut scene = Scene::new(Rect::from_w_h(100.0, 50.0), Srgb::new(255u8, 255, 255));
+        let (dark, light) = (Srgb::new(0x20u8, 0x30, 0x40), Srgb::new(0xd8u8, 0xf3, 0xe8));
+        scene.polyline(vec![Vec2::new(0.0, 0.0), Vec2::new(10.0, 10.0)], dark, 1.0);
+        scene.ellipse(Vec2::new(0.0, 0.0), 2.0, light);
+        scene.polyline(vec![Vec2::new(5.0, 0.0), Vec2::new(10.0, 0.0)], dark, 1.0);
+        scene.shapes.push(crate::scene::Shape::Polygon {
+            points: vec![
+                Vec2::new(0.0, 0.0),
+                Vec2::new(5.0, 0.0),
+                Vec2::new(5.0, 5.0),
+            ],
+            fill: Some(light.into()),
+            stroke: Some(crate::scene::Stroke {
+                color: dark.into(),
+                weight: 0.5,
+                registration: false,
+            }),
+        });
+        let svg = render_layers(&scene);
+
+        let light_layer = svg
+            .find(r##"<g inkscape:groupmode="layer" inkscape:label="#d8f3e8" id="layer1">"##)
+            .expect(&svg);
+        let dark_layer = svg
+            .find(r##"<g inkscape:groupmode="layer" inkscape:label="#203040" id="layer2">"##)
+            .expect(&svg);
+        assert!(light_layer < dark_layer);
+        assert_eq!(svg.matches("<g ").count(), 2);
+        // The stroked triangle lands in both layers: filled in the light one, outlined in the dark.
+        let (light, dark) = svg.split_at(dark_layer);
+        assert_eq!(light.matches("<polygon").count(), 1);
+        assert_eq!(dark.matches("<polygon").count(), 1);
+        assert_eq!(dark.matches("<polyline").count(), 2);
+        assert!(
+            dark.contains(r##"fill="none" stroke="#203040""##),
+            "{}",
+            svg
+        );
+    }
+}
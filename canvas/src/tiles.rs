@@ -0,0 +1,411 @@
+//! Renders images too large to hold in memory, or to fit in one GPU texture, as a grid of tiles
+//! stitched into a single PNG or TIFF on disk one row of tiles at a time.
+//!
+//! Each tile is rendered with the viewport moved to its corner and `--tile-overlap` pixels to spare
+//! on every side, which are cropped off again when stitching. Only the row of tiles being written is
+//! ever in memory. Images wider or taller than `MAX_UNTILED` pixels, or written to a `.tif` path, are
+//! always tiled, and `--tile-size` tiles smaller ones too. `--compression` applies to PNGs.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::thread;
+
+use image::RgbaImage;
+
+use crate::args::Args;
+use crate::encode::Compression;
+use crate::metadata::{self, Metadata};
+use crate::raster;
+use crate::scene::Scene;
+
+/// The widest or tallest image rendered in one piece unless tiling is asked for.
+pub const MAX_UNTILED: u32 = 8192;
+const DEFAULT_TILE_SIZE: u32 = 1024;
+const DEFAULT_OVERLAP: u32 = 16;
+/// How much compressed image data goes into each PNG `IDAT` chunk.
+const IDAT_SIZE: usize = 1 << 20;
+/// The PNG filter that stores each byte as the difference from the pixel on its left.
+const SUB_FILTER: u8 = 1;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TileFormat {
+    Png,
+    Tiff,
+}
+
+impl TileFormat {
+    /// The format to write to `path`: TIFF for `.tif` and `.tiff`, PNG otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        match extension.to_lowercase().as_str() {
+            "tif" | "tiff" => TileFormat::Tiff,
+            _ => TileFormat::Png,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TileSettings {
+    /// The width and height of a tile in pixels, before the overlap.
+    pub size: u32,
+    pub overlap: u32,
+    pub compression: Compression,
+}
+
+impl Default for TileSettings {
+    fn default() -> Self {
+        TileSettings {
+            size: DEFAULT_TILE_SIZE,
+            overlap: DEFAULT_OVERLAP,
+            compression: Compression::Default,
+        }
+    }
+}
+
+impl TileSettings {
+    /// Read `--tile-size`, `--tile-overlap` and `--compression`.
+    pub fn from_args(args: &Args) -> Self {
+        TileSettings {
+            size: args.parsed("tile-size").unwrap_or(DEFAULT_TILE_SIZE).max(1),
+            overlap: args.parsed("tile-overlap").unwrap_or(DEFAULT_OVERLAP),
+            compression: args.parsed("compression").unwrap_or(Compression::Default),
+        }
+    }
+}
+
+/// One tile of the grid, in pixels of the whole image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The tiles covering a `width` x `height` image, a row at a time from the top.
+pub fn grid(width: u32, height: u32, size: u32) -> Vec<Vec<Tile>> {
+    let size = size.max(1);
+    (0..height)
+        .step_by(size as usize)
+        .map(|y| {
+            (0..width)
+                .step_by(size as usize)
+                .map(|x| Tile {
+                    x,
+                    y,
+                    width: size.min(width - x),
+                    height: size.min(height - y),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Save `scene` at `width` x `height` to `path` stamped with `metadata` and the size, tiled if the
+/// command line, the size or the format calls for it and in one piece otherwise.
+pub fn save_requested(
+    scene: &Scene,
+    (width, height): (u32, u32),
+    path: &Path,
+    metadata: &Metadata,
+    args: &Args,
+) -> io::Result<()> {
+    let metadata = &metadata
+        .clone()
+        .with(metadata::SIZE, format!("{}x{}", width, height));
+    let tiled = args.flag("tile-size")
+        || width.max(height) > MAX_UNTILED
+        || TileFormat::from_path(path) == TileFormat::Tiff;
+    if tiled {
+        return save(
+            scene,
+            width,
+            height,
+            path,
+            metadata,
+            &TileSettings::from_args(args),
+        );
+    }
+    raster::save(scene, width, height, path).map_err(io::Error::other)?;
+    metadata.stamp(path)
+}
+
+/// Render `scene` tile by tile at `width` x `height` and stitch it into a PNG or TIFF at `path`,
+/// depending on its extension. The file appears once it is complete.
+pub fn save(
+    scene: &Scene,
+    width: u32,
+    height: u32,
+    path: &Path,
+    metadata: &Metadata,
+    settings: &TileSettings,
+) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let partial = path.with_extension(format!("{}.partial", extension));
+    let file = BufWriter::new(File::create(&partial)?);
+    match TileFormat::from_path(path) {
+        TileFormat::Png => {
+            let mut png = PngStream::create(file, width, height, metadata, settings.compression)?;
+            stitch(scene, width, height, settings, |rows| png.write_rows(rows))?;
+            png.finish()?;
+        }
+        TileFormat::Tiff => write_tiff(file, scene, width, height, metadata, settings)?,
+    }
+    fs::rename(&partial, path)
+}
+
+/// Render the tiles a row at a time, passing each row of tiles on as RGBA rows of the whole width.
+fn stitch(
+    scene: &Scene,
+    width: u32,
+    height: u32,
+    settings: &TileSettings,
+    mut write: impl FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<()> {
+    let rows = grid(width, height, settings.size);
+    for (index, row) in rows.iter().enumerate() {
+        // The tiles of a row are rendered side by side, each on its own thread.
+        let rendered: Vec<RgbaImage> = thread::scope(|s| {
+            let threads: Vec<_> = row
+                .iter()
+                .map(|&tile| s.spawn(move || render_tile(scene, width, height, tile, settings)))
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().expect("a tile rendering thread panicked"))
+                .collect()
+        });
+        let band_height = row[0].height;
+        let mut band = Vec::with_capacity(width as usize * band_height as usize * 4);
+        for y in 0..band_height {
+            for image in &rendered {
+                let start = (y * image.width()) as usize * 4;
+                band.extend_from_slice(&image.as_raw()[start..start + image.width() as usize * 4]);
+            }
+        }
+        write(&band)?;
+        println!("Stitched tile row {} of {}", index + 1, rows.len());
+    }
+    Ok(())
+}
+
+/// Render `tile` with its overlap, then crop the overlap off again.
+fn render_tile(
+    scene: &Scene,
+    width: u32,
+    height: u32,
+    tile: Tile,
+    settings: &TileSettings,
+) -> RgbaImage {
+    let x = tile.x.saturating_sub(settings.overlap);
+    let y = tile.y.saturating_sub(settings.overlap);
+    let right = (tile.x + tile.width + settings.overlap).min(width);
+    let bottom = (tile.y + tile.height + settings.overlap).min(height);
+    let rendered = raster::render_region(scene, width, (x, y, right - x, bottom - y));
+    image::imageops::crop_imm(&rendered, tile.x - x, tile.y - y, tile.width, tile.height).to_image()
+}
+
+/// An RGBA PNG written a few rows at a time.
+struct PngStream {
+    zlib: deflate::write::ZlibEncoder<IdatWriter>,
+    row_bytes: usize,
+    filtered: Vec<u8>,
+}
+
+impl PngStream {
+    fn create(
+        mut file: BufWriter<File>,
+        width: u32,
+        height: u32,
+        metadata: &Metadata,
+        compression: Compression,
+    ) -> io::Result<Self> {
+        let mut header = metadata::SIGNATURE.to_vec();
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        // 8 bits per channel of RGBA, deflated, filtered by row, not interlaced.
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+        metadata::write_chunk(&mut header, b"IHDR", &ihdr);
+        file.write_all(&metadata.stamped(&header)?)?;
+        let idat = IdatWriter {
+            file,
+            buffer: Vec::with_capacity(IDAT_SIZE),
+        };
+        Ok(PngStream {
+            zlib: deflate::write::ZlibEncoder::new(idat, deflate::Compression::from(compression)),
+            row_bytes: width as usize * 4,
+            filtered: Vec::with_capacity(width as usize * 4 + 1),
+        })
+    }
+
+    fn write_rows(&mut self, rows: &[u8]) -> io::Result<()> {
+        for row in rows.chunks(self.row_bytes) {
+            self.filtered.clear();
+            self.filtered.push(SUB_FILTER);
+            self.filtered.extend_from_slice(&row[..4]);
+            self.filtered.extend(
+                row[4..]
+                    .iter()
+                    .zip(row)
+                    .map(|(byte, left)| byte.wrapping_sub(*left)),
+            );
+            self.zlib.write_all(&self.filtered)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> io::Result<()> {
+        let mut idat = self.zlib.finish()?;
+        idat.write_chunk()?;
+        let mut end = Vec::new();
+        metadata::write_chunk(&mut end, b"IEND", &[]);
+        idat.file.write_all(&end)?;
+        idat.file.into_inner()?.sync_all()
+    }
+}
+
+/// Gathers compressed image data into `IDAT` chunks.
+struct IdatWriter {
+    file: BufWriter<File>,
+    buffer: Vec<u8>,
+}
+
+impl IdatWriter {
+    fn write_chunk(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let mut chunk = Vec::with_capacity(self.buffer.len() + 12);
+        metadata::write_chunk(&mut chunk, b"IDAT", &self.buffer);
+        self.buffer.clear();
+        self.file.write_all(&chunk)
+    }
+}
+
+impl Write for IdatWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() >= IDAT_SIZE {
+            self.write_chunk()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An uncompressed RGBA TIFF, one strip per row of tiles, with the metadata as its description.
+fn write_tiff(
+    file: BufWriter<File>,
+    scene: &Scene,
+    width: u32,
+    height: u32,
+    metadata: &Metadata,
+    settings: &TileSettings,
+) -> io::Result<()> {
+    use tiff::encoder::{colortype, TiffEncoder};
+    use tiff::tags::Tag;
+
+    // Strips are found by 32 bit offsets, so a plain TIFF stops at 4 GB.
+    if width as u64 * height as u64 * 4 > u32::MAX as u64 - (1 << 20) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{}x{} is too large for a TIFF, write a PNG instead",
+                width, height
+            ),
+        ));
+    }
+    let description: Vec<String> = metadata
+        .entries()
+        .iter()
+        .map(|(key, value)| format!("{}: {}", key, value))
+        .collect();
+    let mut tiff = TiffEncoder::new(file).map_err(io::Error::other)?;
+    let mut image = tiff
+        .new_image::<colortype::RGBA8>(width, height)
+        .map_err(io::Error::other)?;
+    image
+        .rows_per_strip(settings.size.min(height).max(1))
+        .map_err(io::Error::other)?;
+    let encoder = image.encoder();
+    encoder
+        .write_tag(Tag::ImageDescription, description.join("\n").as_str())
+        .map_err(io::Error::other)?;
+    if let Some(sketch) = metadata.get(metadata::SKETCH) {
+        let version = metadata.get(metadata::VERSION).unwrap_or("");
+        encoder
+            .write_tag(Tag::Software, format!("{} {}", sketch, version).trim())
+            .map_err(io::Error::other)?;
+    }
+    // The fourth channel is straight, unpremultiplied alpha.
+    encoder
+        .write_tag(Tag::ExtraSamples, 2u16)
+        .map_err(io::Error::other)?;
+    stitch(scene, width, height, settings, |rows| {
+        image.write_strip(rows).map_err(io::Error::other)
+    })?;
+    image.finish().map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encode::Compression;
+    use crate::metadata::{Metadata, SEED};
+    use crate::raster;
+    use crate::scene::Scene;
+    use crate::testing::TempDir;
+    use crate::tiles::{grid, save, TileSettings};
+    use nannou::color::{Srgb, Srgba};
+    use nannou::geom::{Rect, Vec2};
+
+    #[test]
+    fn grids_cover_the_image_with_short_tiles_at_the_edges() {
+        let rows = grid(50, 30, 20);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].len(), 3);
+        assert_eq!((rows[0][2].x, rows[0][2].width), (40, 10));
+        assert_eq!((rows[1][0].y, rows[1][0].height), (20, 10));
+    }
+
+    #[test]
+    fn stitched_tiles_match_a_single_render() {
+        let mut scene = Scene::new(Rect::from_w_h(100.0, 60.0), Srgb::new(250u8, 245, 240));
+        let ink = Srgba::new(20u8, 60, 90, 200);
+        scene.ellipse(Vec2::new(-3.0, 4.0), 21.0, ink);
+        scene.rect(&Rect::from_x_y_w_h(20.0, -10.0, 33.3, 17.7), ink);
+        let line = vec![Vec2::new(-48.0, -27.0), Vec2::new(45.0, 26.0)];
+        scene.polyline(line, Srgba::new(200u8, 40, 40, 255), 1.3);
+        let (width, height) = (137, 83);
+        let whole = raster::render(&scene, width, height);
+
+        let dir = TempDir::new("tiles");
+        let metadata = Metadata::default().with(SEED, 3);
+        let settings = TileSettings {
+            size: 32,
+            overlap: 3,
+            compression: Compression::Fast,
+        };
+        for name in ["tiled.png", "tiled.tif"] {
+            let path = dir.join(name);
+            save(&scene, width, height, &path, &metadata, &settings).unwrap();
+            let stitched = image::open(&path).unwrap().to_rgba8();
+            // Moving the viewport can round a coverage the other way, but never by more than that.
+            let largest_difference = stitched
+                .as_raw()
+                .iter()
+                .zip(whole.as_raw())
+                .map(|(a, b)| a.abs_diff(*b))
+                .max();
+            assert!(largest_difference <= Some(1), "{}", name);
+        }
+        assert_eq!(Metadata::read(&dir.join("tiled.png")).unwrap(), metadata);
+    }
+}
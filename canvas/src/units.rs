@@ -0,0 +1,194 @@
+//! Physical lengths for print geometry.
+//!
+//! Each unit is its own type so that millimetres and inches cannot be mixed up by accident.
+//! Conversions between them go through `From` and are exact up to `f64` precision. Pixels only
+//! have a physical size at a given DPI, so they are converted to and from with `to_px` and
+//! `from_px` rather than having a type of their own.
+
+use std::fmt;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
+use serde::{Deserialize, Serialize};
+
+pub const MM_PER_INCH: f64 = 25.4;
+pub const POINTS_PER_INCH: f64 = 72.0;
+
+/// Millimetres.
+#[derive(Copy, Clone, Debug, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct Mm(pub f64);
+
+/// Inches.
+#[derive(Copy, Clone, Debug, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct Inch(pub f64);
+
+/// PostScript points, 1/72 of an inch, as used by PDF and most print tools.
+#[derive(Copy, Clone, Debug, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct Pt(pub f64);
+
+macro_rules! length {
+    ($unit:ident, $suffix:expr) => {
+        impl $unit {
+            /// The length in pixels at `dpi` pixels per inch, unrounded.
+            pub fn to_px(self, dpi: f64) -> f64 {
+                Inch::from(self).0 * dpi
+            }
+
+            /// The length of `px` pixels at `dpi` pixels per inch.
+            pub fn from_px(px: f64, dpi: f64) -> Self {
+                Inch(px / dpi).into()
+            }
+
+            pub fn min(self, other: Self) -> Self {
+                $unit(self.0.min(other.0))
+            }
+
+            pub fn max(self, other: Self) -> Self {
+                $unit(self.0.max(other.0))
+            }
+        }
+
+        impl Add for $unit {
+            type Output = Self;
+            fn add(self, rhs: Self) -> Self {
+                $unit(self.0 + rhs.0)
+            }
+        }
+
+        impl AddAssign for $unit {
+            fn add_assign(&mut self, rhs: Self) {
+                self.0 += rhs.0;
+            }
+        }
+
+        impl Sub for $unit {
+            type Output = Self;
+            fn sub(self, rhs: Self) -> Self {
+                $unit(self.0 - rhs.0)
+            }
+        }
+
+        impl SubAssign for $unit {
+            fn sub_assign(&mut self, rhs: Self) {
+                self.0 -= rhs.0;
+            }
+        }
+
+        impl Mul<f64> for $unit {
+            type Output = Self;
+            fn mul(self, rhs: f64) -> Self {
+                $unit(self.0 * rhs)
+            }
+        }
+
+        impl Div<f64> for $unit {
+            type Output = Self;
+            fn div(self, rhs: f64) -> Self {
+                $unit(self.0 / rhs)
+            }
+        }
+
+        /// The ratio between two lengths.
+        impl Div for $unit {
+            type Output = f64;
+            fn div(self, rhs: Self) -> f64 {
+                self.0 / rhs.0
+            }
+        }
+
+        impl Neg for $unit {
+            type Output = Self;
+            fn neg(self) -> Self {
+                $unit(-self.0)
+            }
+        }
+
+        impl fmt::Display for $unit {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Display::fmt(&self.0, f)?;
+                write!(f, $suffix)
+            }
+        }
+    };
+}
+
+length!(Mm, "mm");
+length!(Inch, "in");
+length!(Pt, "pt");
+
+impl Mm {
+    /// The number of millimetres as an `f32`, for scene and plot geometry laid out in millimetres.
+    pub fn as_f32(self) -> f32 {
+        self.0 as f32
+    }
+}
+
+impl From<Inch> for Mm {
+    fn from(inch: Inch) -> Self {
+        Mm(inch.0 * MM_PER_INCH)
+    }
+}
+
+impl From<Mm> for Inch {
+    fn from(mm: Mm) -> Self {
+        Inch(mm.0 / MM_PER_INCH)
+    }
+}
+
+impl From<Pt> for Inch {
+    fn from(pt: Pt) -> Self {
+        Inch(pt.0 / POINTS_PER_INCH)
+    }
+}
+
+impl From<Inch> for Pt {
+    fn from(inch: Inch) -> Self {
+        Pt(inch.0 * POINTS_PER_INCH)
+    }
+}
+
+impl From<Pt> for Mm {
+    fn from(pt: Pt) -> Self {
+        Mm(pt.0 * MM_PER_INCH / POINTS_PER_INCH)
+    }
+}
+
+impl From<Mm> for Pt {
+    fn from(mm: Mm) -> Self {
+        Pt(mm.0 * POINTS_PER_INCH / MM_PER_INCH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::units::{Inch, Mm, Pt};
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn conversions_are_exact() {
+        assert_eq!(Mm::from(Inch(1.0)), Mm(25.4));
+        assert_eq!(Pt::from(Inch(8.5)), Pt(612.0));
+        assert!(close(Mm::from(Pt(72.0)).0, 25.4));
+        assert!(close(Pt::from(Mm(297.0)).0, 841.8897637795276));
+        assert!(close(Mm::from(Inch::from(Mm(123.456))).0, 123.456));
+    }
+
+    #[test]
+    fn pixels_at_a_dpi() {
+        assert!(close(Mm(210.0).to_px(300.0), 2480.314960629921));
+        assert!(close(Mm(297.0).to_px(300.0), 3507.874015748031));
+        assert!(close(Inch(2.0).to_px(150.0), 300.0));
+        assert!(close(Pt(72.0).to_px(96.0), 96.0));
+        assert!(close(Mm::from_px(600.0, 600.0).0, 25.4));
+    }
+
+    #[test]
+    fn arithmetic_stays_in_the_unit() {
+        let margin = Mm(12.5);
+        assert_eq!(Mm(210.0) - margin * 2.0, Mm(185.0));
+        assert_eq!(Mm(10.0) / Mm(4.0), 2.5);
+        assert_eq!(Inch(3.0).to_string(), "3in");
+    }
+}
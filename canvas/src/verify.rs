@@ -0,0 +1,195 @@
+//! Checks a directory of recorded frames for gaps, damaged files and frames left half written, as
+//! used to resume a recording and by the `verify-frames` tool.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::metadata;
+use crate::record::{FrameRange, NameTemplate};
+
+/// What a frames directory holds.
+#[derive(Debug, Default)]
+pub struct FrameReport {
+    /// Frames that are whole PNGs, by number.
+    pub valid: BTreeSet<u64>,
+    /// Frames that are there but damaged, and what is wrong with them.
+    pub corrupt: Vec<(PathBuf, String)>,
+    /// Frames an interrupted recording was still writing.
+    pub partial: Vec<PathBuf>,
+}
+
+impl FrameReport {
+    /// The frames of `range` that are not valid, up to its end or, if it has none, the last valid
+    /// frame.
+    pub fn missing(&self, range: &FrameRange) -> Vec<u64> {
+        let end = match (range.end, self.valid.last()) {
+            (Some(end), _) => end,
+            (None, Some(&last)) => last + 1,
+            (None, None) => return Vec::new(),
+        };
+        (range.start..end)
+            .step_by(range.every.max(1) as usize)
+            .filter(|nth| !self.valid.contains(nth))
+            .collect()
+    }
+
+    /// The first frame of `range` still to be recorded.
+    pub fn first_missing(&self, range: &FrameRange) -> u64 {
+        (range.start..)
+            .step_by(range.every.max(1) as usize)
+            .find(|nth| !self.valid.contains(nth))
+            .expect("a recording cannot hold every frame")
+    }
+}
+
+/// Look through `dir` for frames named by `template`. Without a `sketch` or `seed`, frames of any
+/// sketch or seed are taken. A directory that does not exist yet holds no frames.
+pub fn scan(
+    dir: &Path,
+    template: &NameTemplate,
+    sketch: Option<&str>,
+    seed: Option<u64>,
+) -> io::Result<FrameReport> {
+    let mut report = FrameReport::default();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(report),
+        Err(err) => return Err(err),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+        if let Some(finished) = name.strip_suffix(".partial") {
+            if template.frame_number(finished, sketch, seed).is_some() {
+                report.partial.push(path.clone());
+            }
+            continue;
+        }
+        let nth = match template.frame_number(name, sketch, seed) {
+            Some(nth) => nth,
+            None => continue,
+        };
+        match check_png(&path) {
+            Ok(()) => {
+                report.valid.insert(nth);
+            }
+            Err(err) => report.corrupt.push((path, err.to_string())),
+        }
+    }
+    report.corrupt.sort();
+    report.partial.sort();
+    Ok(report)
+}
+
+/// Whether the file at `path` is a whole PNG: a header, image data and the end marker, with every
+/// chunk's checksum intact.
+pub fn check_png(path: &Path) -> io::Result<()> {
+    let png = fs::read(path)?;
+    let chunks = metadata::chunks(&png)?;
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+    if chunks.first().map(|chunk| &chunk.kind) != Some(b"IHDR") {
+        return Err(invalid("does not start with a PNG header"));
+    }
+    if !chunks.iter().any(|chunk| &chunk.kind == b"IDAT") {
+        return Err(invalid("has no image data"));
+    }
+    if chunks.last().map(|chunk| &chunk.kind) != Some(b"IEND") {
+        return Err(invalid("is cut off before its end"));
+    }
+    for chunk in &chunks {
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(&chunk.kind);
+        crc.update(&png[chunk.data.clone()]);
+        if crc.finalize().to_be_bytes() != png[chunk.data.end..chunk.end] {
+            return Err(invalid(&format!(
+                "has a damaged {} chunk",
+                String::from_utf8_lossy(&chunk.kind)
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Frame numbers written as runs, like `3, 7..=9, 12`, where a run steps by `every`.
+pub struct Runs<'a> {
+    pub frames: &'a [u64],
+    pub every: u64,
+}
+
+impl fmt::Display for Runs<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut runs: Vec<(u64, u64)> = Vec::new();
+        for &nth in self.frames {
+            match runs.last_mut() {
+                Some((_, last)) if *last + self.every.max(1) == nth => *last = nth,
+                _ => runs.push((nth, nth)),
+            }
+        }
+        let runs: Vec<String> = runs
+            .iter()
+            .map(|&(first, last)| {
+                if first == last {
+                    first.to_string()
+                } else {
+                    format!("{}..={}", first, last)
+                }
+            })
+            .collect();
+        write!(f, "{}", runs.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record::{FrameRange, NameTemplate};
+    use crate::testing::TempDir;
+    use crate::verify::{scan, Runs};
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn gaps_and_damaged_frames_are_found() {
+        let dir = TempDir::new("verify");
+        let template = NameTemplate::default();
+        let frame = RgbaImage::from_pixel(2, 2, Rgba([1, 2, 3, 255]));
+        for nth in [0, 1, 2, 5, 6] {
+            frame.save(dir.join(format!("{:05}.png", nth))).unwrap();
+        }
+        // Frame 2 loses its end, frame 5 has a byte flipped, frame 3 was still being written.
+        let cut = dir.join("00002.png");
+        let png = std::fs::read(&cut).unwrap();
+        std::fs::write(&cut, &png[..png.len() - 12]).unwrap();
+        let flipped = dir.join("00005.png");
+        let mut png = std::fs::read(&flipped).unwrap();
+        let inside_image_data = png.len() - 20;
+        png[inside_image_data] ^= 0xff;
+        std::fs::write(&flipped, png).unwrap();
+        std::fs::write(dir.join("00003.png.partial"), b"").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"").unwrap();
+
+        let report = scan(dir.path(), &template, None, None).unwrap();
+        assert_eq!(report.valid.iter().copied().collect::<Vec<_>>(), [0, 1, 6]);
+        assert_eq!(report.corrupt.len(), 2);
+        assert!(report.corrupt[0].1.contains("cut off"));
+        assert!(report.corrupt[1].1.contains("damaged"));
+        assert_eq!(report.partial, vec![dir.join("00003.png.partial")]);
+
+        let all = FrameRange::all();
+        assert_eq!(report.missing(&all), vec![2, 3, 4, 5]);
+        assert_eq!(report.first_missing(&all), 2);
+        let missing = report.missing(&FrameRange::first(9));
+        assert_eq!(
+            Runs {
+                frames: &missing,
+                every: 1
+            }
+            .to_string(),
+            "2..=5, 7..=8"
+        );
+    }
+}
@@ -1,16 +1,42 @@
-use std::collections::HashSet;
-
-use fills;
-
+#![allow(
+    clippy::needless_return,
+    clippy::map_flatten,
+    clippy::len_zero,
+    clippy::unnecessary_cast,
+    clippy::ptr_arg,
+    clippy::explicit_auto_deref,
+    clippy::needless_borrow,
+    clippy::bool_assert_comparison,
+    clippy::assign_op_pattern
+)]
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use canvas::args::Args;
+use canvas::metadata::Metadata;
+use canvas::offline::Offline;
+use canvas::paper::Paper;
+use canvas::record::{FrameRange, Recording};
+use canvas::units::Mm;
+use canvas::Scene;
 use log::debug;
-use nannou::color::rgba8;
-use nannou::{prelude::*, rand::prelude::SliceRandom, rand::thread_rng, rand::random_range};
-
-mod paper;
-
-const MAX_RECORDABLE_FRAMES: u64 = 1;
-const PADDING: f32 = 15.0;
-const MARGIN: f32 = 100.0;
+use serde::{Deserialize, Serialize};
+use nannou::{prelude::*, rand::prelude::SliceRandom, rand::Rng};
+
+// The composition is laid out in millimetres on the paper, so it looks the same at any DPI.
+const PADDING: Mm = Mm(2.5);
+const MARGIN: Mm = Mm(15.0);
+// Range of cell sizes the grid is divided into.
+const CELL_SIZE: (Mm, Mm) = (Mm(1.0), Mm(50.0));
+// Range of spacings between the points of dot and mesh fills.
+const FILL_SPACING: (Mm, Mm) = (Mm(1.0), Mm(3.5));
+// How far dots are jittered from their grid positions.
+const DOT_OFFSET: Mm = Mm(1.0);
+const DOT_RADIUS: Mm = Mm(0.17);
+const LINE_WEIGHT: Mm = Mm(0.17);
+const OUTLINE_WEIGHT: Mm = Mm(0.34);
+const DEFAULT_DPI: u16 = 150;
 // const WEIGHTED_COLORS: [((u8, u8, u8), f32); 5] = [
 //     ((0x4C, 0xBF, 0xC7), 0.2),
 //     ((0x82, 0x93, 0x94), 0.2),
@@ -37,7 +63,7 @@ const WEIGHTED_COLORS: [((u8, u8, u8), f32); 6] = [
 ];
 
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 enum FillType {
     Dots,
     Triangles,
@@ -57,9 +83,21 @@ const WEIGHTED_FILL_TYPE: [(FillType, f32); 5] = [
 const CHAIN_MIN: i32 = 4;
 const CHAIN_MAX: i32 = 13;
 const BACKGROUND: (u8, u8, u8) = (0xFD, 0xF9, 0xF5);
+// Distance in millimetres under which line end points are treated as the same point.
+const LINE_TOLERANCE: f32 = 1e-3;
 
 fn main() {
     env_logger::init();
+    let args = Args::from_env();
+    canvas::seed::master();
+    if args.flag("headless") {
+        render_headless(&args);
+        return;
+    }
+    if let Some(offline) = Offline::from_args(&args) {
+        render_offline(&args, &offline);
+        return;
+    }
     nannou::app(model)
         .update(update)
         .simple_window(view)
@@ -67,13 +105,55 @@ fn main() {
         .run();
 }
 
+// Render the composition on the CPU and write it to a PNG, without creating a window or a wgpu device.
+fn render_headless(args: &Args) {
+    let (composition, (w, h)) = headless_composition(args);
+    let path = args
+        .value("out")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("frames").join("cells.png"));
+    let (scene, (w, h)) = canvas::print::requested(scene(&composition), args, (w, h));
+    // Large prints are rendered in tiles, see `canvas::tiles`.
+    let metadata = metadata(&composition);
+    canvas::tiles::save_requested(&scene, (w, h), &path, &metadata, args)
+        .expect("failed to save image");
+    println!("Saved {}", path.display());
+    canvas::export::write_requested(&scene, &metadata, args);
+}
+
+// Step through `--offline` frames of simulated time and record every one, without a window.
+fn render_offline(args: &Args, offline: &Offline) {
+    let (mut composition, size) = headless_composition(args);
+    let recording = Recording::from_args(
+        args,
+        PathBuf::from("frames"),
+        Some(FrameRange::first(offline.frames)),
+        metadata(&composition),
+        canvas::animation::palette(&WEIGHTED_COLORS, BACKGROUND),
+    )
+    .expect("offline renders are always recorded");
+    offline
+        .run(&mut composition, &recording, size, step, scene)
+        .expect("failed to record frame");
+}
+
+// The composition to render without a window, and the size to render it at: the paper at `--dpi`
+// unless a pixel size is asked for.
+fn headless_composition(args: &Args) -> (Composition, (u32, u32)) {
+    let paper = Paper::from_args(args);
+    let composition = canvas::archive::load_or_generate(args, || {
+        compose(&paper, &mut canvas::seed::rng(canvas::seed::master()))
+    });
+    let dpi = args.parsed("dpi").unwrap_or(DEFAULT_DPI);
+    let size = args.size("size").unwrap_or_else(|| paper.pixel_dimensions(dpi));
+    (composition, size)
+}
+
 fn model(app: &App) -> Model {
-    // Let's write to A4 portrait page.
-    // let paper = paper::Paper::from_iso216(paper::ISO216::A4, paper::Orientation::Portrait).unwrap();
-    let dimensions: (u32, u32) = (1440, 2560);
+    let args = Args::from_env();
+    let paper = Paper::from_args(&args);
+    let dimensions = paper.pixel_dimensions(args.parsed("dpi").unwrap_or(DEFAULT_DPI));
     let texture_dimensions = [dimensions.0, dimensions.1];
-    let pixels_per_cell = random_range::<u32>(5, 300);
-    let (columns, rows) = (dimensions.0 / pixels_per_cell, dimensions.1 / pixels_per_cell);
 
     let [win_h, win_w] = [dimensions.0 / 4, dimensions.1 / 4];
     let w_id = app
@@ -124,6 +204,37 @@ fn model(app: &App) -> Model {
         dst_format,
     );
 
+    let composition = canvas::archive::load_or_generate(&args, || {
+        compose(&paper, &mut canvas::seed::rng(canvas::seed::master()))
+    });
+    // Only the first frame is saved unless the command line asks for more, see `canvas::record`.
+    let recording = Recording::from_args(
+        &args,
+        app.project_path()
+            .expect("failed to locate `project_path`")
+            .join("frames"),
+        Some(FrameRange::first(1)),
+        metadata(&composition),
+        canvas::animation::palette(&WEIGHTED_COLORS, BACKGROUND),
+    );
+
+    Model {
+        texture,
+        draw,
+        renderer,
+        texture_capturer,
+        texture_reshaper,
+        recording,
+        composition,
+    }
+}
+
+fn compose<R: Rng>(paper: &Paper, rng: &mut R) -> Composition {
+    let (width, height) = (paper.width().as_f32(), paper.height().as_f32());
+    let cell_size = rng.gen_range(CELL_SIZE.0.as_f32()..CELL_SIZE.1.as_f32());
+    let columns = ((width / cell_size) as u32).max(1);
+    let rows = ((height / cell_size) as u32).max(1);
+
     // STARTING SHAPE BUILDING
     // Create a grid of cells.
     // While there are cells not filled in the grid, fill one at random and then
@@ -132,16 +243,38 @@ fn model(app: &App) -> Model {
     // Repeat while loop.
 
     debug!("Creating grid");
-    let grid = &mut Grid::new(columns, rows);
-    let mut chains: Vec<Chain> = Vec::new();
+    let mut grid = Grid::new(columns, rows);
+    let chain_cells = build_chains(&mut grid, rng);
+
+    let mut composition = Composition {
+        rect: Rect::from_w_h(width, height),
+        w: columns,
+        h: rows,
+        chains: Vec::new(),
+        margin: MARGIN.as_f32(),
+        padding: PADDING.as_f32(),
+    };
+    // Resolve every random choice for the fills now, so rendering never draws from the RNG.
+    let chains = chain_cells
+        .into_iter()
+        .map(|cells| Chain::from_cells(cells, &composition, rng))
+        .collect();
+    composition.chains = chains;
+    composition
+}
+
+// Split the whole grid into straight chains of cells, each heading off in a random direction from a
+// random free cell until it runs into a taken cell, the grid edge or its random length limit.
+fn build_chains<R: Rng>(grid: &mut Grid, rng: &mut R) -> Vec<Vec<Cell>> {
+    let mut chains: Vec<Vec<Cell>> = Vec::new();
     // First, pop off random number of empty cells.
 
     while grid.has_cells() {
-        let chain_len = random_range::<i32>(CHAIN_MIN, CHAIN_MAX);
+        let chain_len = rng.gen_range(CHAIN_MIN..CHAIN_MAX);
         let mut chain = Vec::new();
-        let chain_direction = random_direction();
+        let chain_direction = random_direction(rng);
 
-        let (mut x, mut y) = grid.peek_random().unwrap();
+        let (mut x, mut y) = grid.peek_random(rng).unwrap();
 
         debug!("Sampling cell ({},{})", x, y);
         if !grid.cell_taken(&(x, y)) {
@@ -169,100 +302,79 @@ fn model(app: &App) -> Model {
                 }
             }
 
-            chains.push(Chain::from_cells(chain));
+            chains.push(chain);
         }
     }
+    chains
+}
 
-    Model {
-        texture,
-        draw,
-        renderer,
-        texture_capturer,
-        texture_reshaper,
+fn scene(composition: &Composition) -> Scene {
+    let background = Rgb::new(BACKGROUND.0, BACKGROUND.1, BACKGROUND.2);
+    let mut scene = Scene::new(composition.rect, background).in_mm();
 
-        w: columns,
-        h: rows,
-        chains,
-        margin: MARGIN,
-        padding: PADDING,
+    for Chain {
+        rect, color, fill, ..
+    } in &composition.chains
+    {
+        match fill {
+            Fill::Empty => {},
+            Fill::Dots { points, .. } => {
+                points.iter().for_each(|&p| {
+                    scene.ellipse(p, DOT_RADIUS.as_f32(), *color);
+                });
+            },
+            Fill::Solid => {
+                scene.rect(rect, *color);
+            },
+            Fill::Mesh { points, .. } => {
+                scene.polyline(points.clone(), *color, LINE_WEIGHT.as_f32());
+            },
+            Fill::Triangles { lines, .. } => {
+                lines.iter().for_each(|line| {
+                    scene.polyline(line.clone(), *color, LINE_WEIGHT.as_f32());
+                });
+            },
+        }
+
+        scene.rect_outline(rect, *color, OUTLINE_WEIGHT.as_f32());
     }
+    scene
 }
 
-fn update(app: &App, model: &mut Model, _update: Update) {
+fn step(_composition: &mut Composition, _update: Update) {}
+
+fn update(app: &App, model: &mut Model, update: Update) {
+    step(&mut model.composition, update);
+
     // First reset draw state.
     let draw = &model.draw;
     draw.reset();
 
-    // draw like we normally would in the view.
-    let background = Rgb::new(BACKGROUND.0, BACKGROUND.1, BACKGROUND.2);
-    draw.background().color(background);
-    let texture_dimensions = model.texture.size();
-    let window_rect = Rect::from_wh(Vec2::new(
-        texture_dimensions[0] as f32,
-        texture_dimensions[1] as f32,
-    ));
-
-    for Chain {
-        cells,
-        color,
-        fill_type,
-    } in &model.chains
-    {
-        if cells.len() > 0 {
-            // Combine the cells into one rectangle.
-            let chain_rect = build_chain_rect(&cells, model, &window_rect);
-
-            // Fill the rectangle.
-            match *fill_type {
-                FillType::Empty => {},
-                FillType::Dots => {
-                    let density = random_range(5.0, 20.0);
-                    let grid_of_points = fills::evenly_distributed_grid(&chain_rect, density);
-
-                    // Offset all points by a random amount multiplied by offset_scale
-                    let offset_scale: f32 = 5.0;
-                    grid_of_points
-                        .iter()
-                        .map(|p| fills::offset_point_randomly(p, offset_scale))
-                        .filter(|p| chain_rect.contains(*p))
-                        .for_each(|p| {
-                            draw.ellipse().radius(1.0).color(*color).xy(p);
-                        });
-                },
-                FillType::Solid => {
-                    draw.rect()
-                        .wh(chain_rect.wh())
-                        .xy(chain_rect.xy())
-                        .color(*color);
-                },
-                FillType::Mesh => {
-                    let density = random_range(5.0, 20.0);
-                    let points = fills::randomly_ordered_grid_of_points(&chain_rect, density);
-                    draw.polyline().color(*color).weight(1.0).points(points);
-                },
-                FillType::Triangles => {
-                    let levels = random_range(2, 7);
-                    let points = fills::subtriangles(&chain_rect, levels);
-                    points.into_iter().for_each(|tri| {
-                        draw.polyline().color(*color).weight(1.0).points(tri);
-                    });
-                },
-
-            }
-
-            draw.rect()
-                .wh(chain_rect.wh())
-                .xy(chain_rect.xy())
-                .color(rgba8(0, 0, 0, 0))
-                .stroke_color(*color)
-                .stroke_weight(2.0);
-        }
+    // draw like we normally would in the view, scaled from millimetres to texture pixels.
+    let scene = scene(&model.composition);
+    let pixels_per_mm = model.texture.size()[0] as f32 / model.composition.rect.w();
+    scene.draw(&draw.scale(pixels_per_mm));
+
+    if app.elapsed_frames() == 0 {
+        // Vector outputs share the frame that gets captured to PNG.
+        canvas::export::write_requested(
+            &scene,
+            &metadata(&model.composition),
+            &Args::from_env(),
+        );
     }
 
-    if app.elapsed_frames() < MAX_RECORDABLE_FRAMES {
-        // Render our drawing to the texture.
+    let recording = model.recording.as_ref();
+    let nth = recording.map_or(0, Recording::fast_forward) + app.elapsed_frames();
+    if let Some(recording) = recording.filter(|r| r.records(nth)) {
         let window = app.main_window();
         let device = window.device();
+
+        // Wait for room if earlier frames are still being written, so they cannot pile up. Their
+        // snapshots are only read back when the device is polled, which nannou leaves to us.
+        let slot = recording.reserve_polling(|| device.poll(wgpu::Maintain::Poll));
+
+        // Render our drawing to the texture.
         let ce_desc = wgpu::CommandEncoderDescriptor {
             label: Some("texture renderer"),
         };
@@ -283,20 +395,17 @@ fn update(app: &App, model: &mut Model, _update: Update) {
         // Submit the commands for our drawing and texture capture to the GPU.
         window.queue().submit(Some(encoder.finish()));
 
-        // Submit a function for writing our snapshot to a PNG.
+        // Submit a function for recording our snapshot.
         //
         // NOTE: It is essential that the commands for capturing the snapshot are `submit`ted before we
         // attempt to read the snapshot - otherwise we will read a blank texture!
-        let elapsed_frames = app.main_window().elapsed_frames();
-        let path = capture_frame_directory(app)
-            .join(elapsed_frames.to_string())
-            .with_extension("png");
+        let recording = recording.clone();
         snapshot
             .read(move |result| {
                 let image = result.expect("failed to map texture memory").to_owned();
-                image
-                    .save(&path)
-                    .expect("failed to save texture to png image");
+                recording
+                    .add_frame(slot, nth, image)
+                    .expect("failed to record frame");
             })
             .unwrap();
     }
@@ -312,19 +421,39 @@ fn view(_app: &App, model: &Model, frame: Frame) {
 
 // Wait for capture to finish.
 fn exit(app: &App, model: Model) {
-    println!("Waiting for PNG writing to complete...");
+    println!("Waiting for the recording to complete...");
     let window = app.main_window();
     let device = window.device();
     model
         .texture_capturer
         .await_active_snapshots(&device)
         .unwrap();
+    if let Some(recording) = &model.recording {
+        recording.finish().expect("failed to finish the recording");
+    }
     println!("Done!");
 }
 
-fn build_chain_rect(chain: &Vec<Cell>, model: &Model, window: &Rect) -> Rect {
+/// What went into the composition, stamped into every PNG so that it can be rendered again.
+fn metadata(composition: &Composition) -> Metadata {
+    let fill_weights: Vec<String> = WEIGHTED_FILL_TYPE
+        .iter()
+        .map(|(fill_type, weight)| format!("{:?} {}", fill_type, weight))
+        .collect();
+    Metadata::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+        .with("Palette", canvas::metadata::palette(&WEIGHTED_COLORS))
+        .with("Fill weights", fill_weights.join(", "))
+        .with("Grid", format!("{}x{}", composition.w, composition.h))
+        .with(
+            "Paper",
+            format!("{}x{}mm", composition.rect.w(), composition.rect.h()),
+        )
+}
+
+fn build_chain_rect(chain: &Vec<Cell>, model: &Composition) -> Rect {
     // Takes a vec of cells and returns the dimensions and position of a rectangle
     // that wraps all of the cells and accounts for any padding and margin.
+    let window = &model.rect;
     let first_cell = chain.first().unwrap();
     let last_cell = chain.last().unwrap();
     // Height and width without padding.
@@ -348,55 +477,90 @@ fn build_chain_rect(chain: &Vec<Cell>, model: &Model, window: &Rect) -> Rect {
     Rect::from_xy_wh(Point2::new(rect_x, rect_y), Vec2::new(rect_w, rect_h))
 }
 
-fn random_direction() -> Direction {
-    let mut rng = thread_rng();
-
+fn random_direction<R: Rng>(rng: &mut R) -> Direction {
     let result = [
         (Direction::UP, 0.25),
         (Direction::DOWN, 0.25),
         (Direction::LEFT, 0.25),
         (Direction::RIGHT, 0.25),
     ]
-    .choose_weighted(&mut rng, |dir| dir.1);
+    .choose_weighted(rng, |dir| dir.1);
     result.unwrap().0
 }
 
-fn capture_frame_directory(app: &App) -> std::path::PathBuf {
-    app.project_path()
-        .expect("failed to locate `project_path`")
-        // Capture all frames to a directory called `<path_to_project>/frames`.
-        .join("frames")
-}
-
+#[derive(Serialize, Deserialize)]
 struct Chain {
     cells: Vec<Cell>,
+    // The cells combined into one rectangle, inset by the padding.
+    #[serde(with = "canvas::archive::rect")]
+    rect: Rect,
     color: Srgb<u8>,
-    fill_type: FillType,
+    fill: Fill,
 }
 
 impl Chain {
-    fn new(cells: Vec<Cell>, color: Srgb<u8>, fill_type: FillType) -> Self {
-        Chain {
-            cells,
-            color,
-            fill_type,
-        }
-    }
-
-    fn from_cells(cells: Vec<Cell>) -> Self {
-        let mut rng = thread_rng();
+    fn from_cells<R: Rng>(cells: Vec<Cell>, composition: &Composition, rng: &mut R) -> Self {
         // Randomly select the color and fill type for the rest of the chain.
         let color_bytes = WEIGHTED_COLORS
-            .choose_weighted(&mut rng, |item| item.1)
+            .choose_weighted(rng, |item| item.1)
             .unwrap()
             .0;
         let color = Rgb::new(color_bytes.0, color_bytes.1, color_bytes.2);
 
         let fill_type = WEIGHTED_FILL_TYPE
-            .choose_weighted(&mut rng, |item| item.1)
+            .choose_weighted(rng, |item| item.1)
             .unwrap()
             .0;
-        Chain::new(cells, color, fill_type)
+        let rect = build_chain_rect(&cells, composition);
+        let fill = Fill::generate(fill_type, &rect, rng);
+        Chain {
+            cells,
+            rect,
+            color,
+            fill,
+        }
+    }
+}
+
+// A fill with all of its random parameters chosen and its geometry generated. The parameters are
+// not needed for drawing but are kept so a chain fully describes how it was made.
+#[derive(Serialize, Deserialize)]
+enum Fill {
+    Empty,
+    Dots { density: f32, points: Vec<Vec2> },
+    Solid,
+    Mesh { density: f32, points: Vec<Vec2> },
+    Triangles { levels: i32, lines: Vec<Vec<Vec2>> },
+}
+
+impl Fill {
+    fn generate<R: Rng>(fill_type: FillType, rect: &Rect, rng: &mut R) -> Self {
+        match fill_type {
+            FillType::Empty => Fill::Empty,
+            FillType::Dots => {
+                let density = 100.0 / rng.gen_range(FILL_SPACING.0.as_f32()..FILL_SPACING.1.as_f32());
+                // Offset all points by a random amount multiplied by offset_scale
+                let offset_scale: f32 = DOT_OFFSET.as_f32();
+                let points = fills::evenly_distributed_grid(rect, density)
+                    .iter()
+                    .map(|p| fills::offset_point_randomly(p, offset_scale, rng))
+                    .filter(|p| rect.contains(*p))
+                    .collect();
+                Fill::Dots { density, points }
+            },
+            FillType::Solid => Fill::Solid,
+            FillType::Mesh => {
+                let density = 100.0 / rng.gen_range(FILL_SPACING.0.as_f32()..FILL_SPACING.1.as_f32());
+                let points = fills::randomly_ordered_grid_of_points(rect, density, rng);
+                Fill::Mesh { density, points }
+            },
+            FillType::Triangles => {
+                let levels = rng.gen_range(2..7);
+                let triangles = fills::subtriangles(rect, levels);
+                let lines = canvas::lines::simplify(&triangles, LINE_TOLERANCE);
+                Fill::Triangles { levels, lines }
+            },
+        }
     }
 }
 
@@ -411,7 +575,16 @@ struct Model {
     texture_capturer: wgpu::TextureCapturer,
     // The type used to resize our texture to the window texture.
     texture_reshaper: wgpu::TextureReshaper,
+    // Where captured frames are recorded to.
+    recording: Option<Recording>,
     // Art fields BELOW
+    composition: Composition,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Composition {
+    #[serde(with = "canvas::archive::rect")]
+    rect: Rect,
     w: u32,
     h: u32,
     margin: f32,
@@ -420,6 +593,7 @@ struct Model {
 }
 
 #[derive(Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
 enum Direction {
     UP,
     DOWN,
@@ -430,41 +604,50 @@ enum Direction {
 type Cell = (i32, i32);
 
 struct Grid {
-    available_cells: HashSet<Cell>,
+    // Cells not taken yet. The order only changes through `take_cell`, so random picks depend on
+    // nothing but the RNG.
+    available_cells: Vec<Cell>,
+    // Position of every available cell in `available_cells`.
+    index: HashMap<Cell, usize>,
     w: u32,
     h: u32,
 }
 
 impl Grid {
     fn new(w: u32, h: u32) -> Self {
-        let available_cells: HashSet<Cell> = (0..w)
+        let available_cells: Vec<Cell> = (0..w)
             .map(|i| return (0..h).map(|j| (i as i32, j as i32)).collect::<Vec<Cell>>())
             .flatten()
             .collect();
+        let index = available_cells
+            .iter()
+            .enumerate()
+            .map(|(i, &cell)| (cell, i))
+            .collect();
         Grid {
             available_cells,
+            index,
             w,
             h,
         }
     }
 
-    fn peek_random(&self) -> Option<Cell> {
-        let v: Vec<Cell> = self.available_cells.iter().map(|&(a, b)| (a, b)).collect();
-        let mut rng = thread_rng();
-        let r = v.choose(&mut rng)?;
-        Some((r.0, r.1))
+    fn peek_random<R: Rng>(&self, rng: &mut R) -> Option<Cell> {
+        self.available_cells.choose(rng).copied()
     }
 
     fn cell_taken(&self, cell: &Cell) -> bool {
-        return !self.available_cells.contains(cell);
+        return !self.index.contains_key(cell);
     }
 
     fn take_cell(&mut self, cell: &Cell) -> Option<Cell> {
-        if self.cell_taken(cell) {
-            None
-        } else {
-            self.available_cells.take(cell)
+        let i = self.index.remove(cell)?;
+        // Fill the hole with the last cell so removal stays O(1).
+        self.available_cells.swap_remove(i);
+        if let Some(&moved) = self.available_cells.get(i) {
+            self.index.insert(moved, i);
         }
+        Some(*cell)
     }
 
     fn has_cells(&self) -> bool {
@@ -539,8 +722,9 @@ mod tests {
     fn test_take_random_cells() {
         let mut grid = crate::Grid::new(10, 10);
         let mut count_taken = 0;
+        let mut rng = canvas::seed::rng(1);
 
-        while let Some(cell) = grid.peek_random() {
+        while let Some(cell) = grid.peek_random(&mut rng) {
             if let Some(taken) = grid.take_cell(&cell) {
                 assert_eq!(taken, cell);
                 count_taken = count_taken + 1;
@@ -551,6 +735,51 @@ mod tests {
         assert_eq!(count_taken, 10 * 10);
     }
 
+    #[test]
+    fn random_peeks_follow_the_rng() {
+        let peeks = |seed| {
+            let mut grid = crate::Grid::new(12, 9);
+            let mut rng = canvas::seed::rng(seed);
+            let mut taken = Vec::new();
+            while let Some(cell) = grid.peek_random(&mut rng) {
+                taken.push(grid.take_cell(&cell).unwrap());
+            }
+            taken
+        };
+        assert_eq!(peeks(5), peeks(5));
+        assert_ne!(peeks(5), peeks(6));
+    }
+
+    #[test]
+    fn taking_cells_keeps_the_index_in_step() {
+        let mut grid = crate::Grid::new(4, 4);
+        for cell in [(0, 0), (3, 3), (1, 2), (0, 0)] {
+            grid.take_cell(&cell);
+        }
+        assert_eq!(grid.available_cells.len(), 13);
+        for (i, cell) in grid.available_cells.iter().enumerate() {
+            assert_eq!(grid.index[cell], i);
+        }
+        assert!(grid.cell_taken(&(1, 2)));
+        assert!(!grid.cell_taken(&(2, 1)));
+    }
+
+    #[test]
+    fn chains_cover_a_million_cell_grid() {
+        let mut grid = crate::Grid::new(1000, 1000);
+        let mut rng = canvas::seed::rng(1);
+        let start = std::time::Instant::now();
+        let chains = crate::build_chains(&mut grid, &mut rng);
+        // A few seconds even in a debug build. The bound is loose enough for slow machines but
+        // still catches a return to scanning every free cell per pick.
+        let elapsed = start.elapsed();
+        assert!(elapsed.as_secs() < 30, "took {:?}", elapsed);
+
+        let covered: usize = chains.iter().map(|chain| chain.len()).sum();
+        assert_eq!(covered, 1000 * 1000);
+        assert!(!grid.has_cells());
+    }
+
     #[test]
     fn long_chain_cell_fetching() {
         let mut grid = crate::Grid::new(1, 5);
@@ -558,7 +787,9 @@ mod tests {
         let chain_len = 5;
         while grid.has_cells() {
             let mut chain = Vec::new();
-            let chain_direction = crate::Direction::RIGHT;
+            // `Grid::new(1, 5)` is one cell wide and five tall, so a chain running right ends after
+            // its first cell. Only a vertical chain can reach the five cells the test expects.
+            let chain_direction = crate::Direction::UP;
 
             let (mut x, mut y) = (0, 0);
 
@@ -578,28 +809,8 @@ mod tests {
                     break;
                 }
             }
-
-            if !grid.cell_taken(&(x, y)) {
-                let starting_cell: crate::Cell = grid.take_cell(&(x, y)).unwrap();
-                chain.push(starting_cell);
-
-                while let Some(next_cell_coordinates) =
-                    grid.adjacent_cell_coordinates(&(x, y), &chain_direction)
-                {
-                    if let Some(next_cell) = grid.take_cell(&next_cell_coordinates) {
-                        chain.push((next_cell.0, next_cell.1));
-                        x = next_cell.0;
-                        y = next_cell.1;
-                    } else {
-                        break;
-                    }
-
-                    if chain.len() < chain_len as usize {
-                        // end this chain while it's possible.
-                        break;
-                    }
-                }
-            }
+            // The chain stops at the top edge, so the loop always leaves `(x, y)` on a taken cell.
+            assert!(grid.cell_taken(&(x, y)));
             assert_eq!(chain.len(), 5);
         }
     }
@@ -1,5 +1,7 @@
+#![allow(clippy::needless_return, clippy::let_and_return, clippy::map_flatten)]
+
 use nannou::geom::{Rect, Vec2, Tri, Point2};
-use nannou::rand::{thread_rng, random_range};
+use nannou::rand::Rng;
 use nannou::rand::prelude::SliceRandom;
 
 
@@ -27,16 +29,15 @@ pub fn evenly_distributed_grid(rect: &Rect, density: f32) -> Vec<Vec2> {
     return points_in_rect;
 }
 
-pub fn offset_point_randomly(point: &Vec2, offset_scale: f32) -> Vec2 {
-        let new_x = point.x + random_range::<f32>(-1.0, 1.0) * offset_scale;
-        let new_y = point.y + random_range::<f32>(-1.0, 1.0) * offset_scale;
+pub fn offset_point_randomly<R: Rng>(point: &Vec2, offset_scale: f32, rng: &mut R) -> Vec2 {
+        let new_x = point.x + rng.gen_range(-1.0..1.0) * offset_scale;
+        let new_y = point.y + rng.gen_range(-1.0..1.0) * offset_scale;
         Vec2::new(new_x, new_y)
 }
 
-pub fn randomly_ordered_grid_of_points(rect: &Rect, density: f32) -> Vec<Vec2> {
+pub fn randomly_ordered_grid_of_points<R: Rng>(rect: &Rect, density: f32, rng: &mut R) -> Vec<Vec2> {
     let mut point_grid = evenly_distributed_grid(rect, density);
-    let mut rng = thread_rng();
-    point_grid.shuffle(&mut rng);
+    point_grid.shuffle(rng);
     return point_grid;
 }
 
@@ -72,6 +73,11 @@ pub fn subtriangles(rect: &Rect, levels: i32) -> Vec<Vec<Vec2>> {
 #[cfg(test)]
 mod tests {
     use crate::{Rect, Vec2};
+    use nannou::rand::{rngs::StdRng, SeedableRng};
+
+    fn rng(seed: u64) -> StdRng {
+        StdRng::seed_from_u64(seed)
+    }
 
     // These tests just ensure that the functions run without panicking. 
     // Admittedly, these are not great tests.
@@ -85,7 +91,15 @@ mod tests {
     #[test]
     fn test_random_grid() {
         let in_rect = Rect::from_xy_wh(Vec2::new(0.0, 0.0), Vec2::new(10.0, 10.0));
-        let _random_grid = crate::randomly_ordered_grid_of_points(&in_rect, 10.0);
+        let _random_grid = crate::randomly_ordered_grid_of_points(&in_rect, 10.0, &mut rng(1));
+    }
+
+    #[test]
+    fn random_grid_is_reproducible() {
+        let in_rect = Rect::from_xy_wh(Vec2::new(0.0, 0.0), Vec2::new(10.0, 10.0));
+        let a = crate::randomly_ordered_grid_of_points(&in_rect, 50.0, &mut rng(3));
+        let b = crate::randomly_ordered_grid_of_points(&in_rect, 50.0, &mut rng(3));
+        assert_eq!(a, b);
     }
 
     #[test]
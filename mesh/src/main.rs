@@ -1,46 +1,135 @@
+use std::path::PathBuf;
+
+use canvas::args::Args;
+use canvas::metadata::Metadata;
+use canvas::offline::Offline;
+use canvas::record::{FrameRange, Recording};
+use canvas::Scene;
 use geo::algorithm::convex_hull;
 use geo::Coordinate;
 use log::{debug, info};
 use nannou::color;
 use nannou::prelude::*;
-
-const MAX_RECORDABLE_FRAMES: u64 = 20_000;
-const RECORD: bool = false;
+use nannou::rand::Rng;
+use serde::{Deserialize, Serialize};
 
 fn main() {
     env_logger::init();
-    nannou::app(model).update(update).simple_window(view).run();
+    let args = Args::from_env();
+    canvas::seed::master();
+    if args.flag("headless") {
+        render_headless(&args);
+        return;
+    }
+    if let Some(offline) = Offline::from_args(&args) {
+        render_offline(&args, &offline);
+        return;
+    }
+    nannou::app(model)
+        .update(update)
+        .simple_window(view)
+        .exit(exit)
+        .run();
+}
+
+// Render a single frame on the CPU and write it to a PNG, without creating a window.
+fn render_headless(args: &Args) {
+    let (model, (w, h)) = headless_model(args);
+    let path = args
+        .value("out")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("frames").join("mesh.png"));
+    let (scene, (w, h)) = canvas::print::requested(scene(&model), args, (w, h));
+    // Large prints are rendered in tiles, see `canvas::tiles`.
+    let metadata = metadata(&model);
+    canvas::tiles::save_requested(&scene, (w, h), &path, &metadata, args)
+        .expect("failed to save image");
+    println!("Saved {}", path.display());
+    canvas::export::write_requested(&scene, &metadata, args);
+}
+
+// Step through `--offline` frames of simulated time and record every one, without a window.
+fn render_offline(args: &Args, offline: &Offline) {
+    let (mut model, size) = headless_model(args);
+    let recording = Recording::from_args(
+        args,
+        PathBuf::from("frames"),
+        Some(FrameRange::first(offline.frames)),
+        metadata(&model),
+        Vec::new(),
+    )
+    .expect("offline renders are always recorded");
+    offline
+        .run(&mut model, &recording, size, step, scene)
+        .expect("failed to record frame");
+}
+
+// The model to render without a window and the size to render it at. A loaded model renders at
+// the size it was generated at unless told otherwise.
+fn headless_model(args: &Args) -> (Model, (u32, u32)) {
+    let size = args.size("size");
+    let model = canvas::archive::load_or_generate(args, || {
+        let (w, h) = size.unwrap_or((1024, 768));
+        let mut rng = canvas::seed::rng(canvas::seed::master());
+        generate(Rect::from_w_h(w as f32, h as f32), &mut rng)
+    });
+    let size = size.unwrap_or((model.window.w() as u32, model.window.h() as u32));
+    (model, size)
 }
 
+#[derive(Serialize, Deserialize)]
 struct Poly {
     polygon: Vec<Point2>,
     color: color::Hsl,
 }
 
+#[derive(Serialize, Deserialize)]
 struct Model {
+    #[serde(with = "canvas::archive::rect")]
+    window: Rect,
+    // Where frames are recorded to, never archived.
+    #[serde(skip)]
+    recording: Option<Recording>,
     polygons: Vec<Poly>,
+    // Only drawn when debugging, see the commented out markers in `scene`.
     random_points: Vec<Point2>,
     center_points: Vec<Point2>,
 }
 
 fn model(app: &App) -> Model {
+    let args = Args::from_env();
+    let mut model = canvas::archive::load_or_generate(&args, || {
+        let mut rng = canvas::seed::rng(canvas::seed::master());
+        generate(app.window_rect(), &mut rng)
+    });
+    // Nothing is recorded unless the command line asks for it, see `canvas::record`.
+    model.recording = Recording::from_args(
+        &args,
+        app.project_path()
+            .expect("failed to locate `project_path`")
+            .join("frames"),
+        None,
+        metadata(&model),
+        Vec::new(),
+    );
+    model
+}
+
+fn generate<R: Rng>(window: Rect, rng: &mut R) -> Model {
     // Define some randomly dispersed points.
     info!("Generating 400 random vertices");
-    let (min_x, max_x) = (app.window_rect().left(), app.window_rect().right());
-    let (min_y, max_y) = (app.window_rect().bottom(), app.window_rect().top());
+    let (min_x, max_x) = (window.left(), window.right());
+    let (min_y, max_y) = (window.bottom(), window.top());
     let mut vertices: Vec<Point2> = (0..400)
         .map(|_| {
-            Point2::new(
-                random_range::<f32>(min_x, max_x),
-                random_range::<f32>(min_y, max_y),
-            )
+            Point2::new(rng.gen_range(min_x..max_x), rng.gen_range(min_y..max_y))
         })
         .collect();
     info!("Done with random vertices");
 
     let mut polygons: Vec<Poly> = Vec::new();
     let mut sample_points: Vec<Point2> = Vec::new();
-    let default_poly_hue = random_range::<f32>(0.0, 1.0);
+    let default_poly_hue = rng.gen_range(0.0..1.0);
 
     // Iterate over a uniform grid of points.
     for x_idx in 1..=20 {
@@ -69,59 +158,72 @@ fn model(app: &App) -> Model {
                 .iter()
                 .map(|&p| Point2::new(p.x(), p.y()))
                 .collect();
-            let color = color::hsl(
-                default_poly_hue,
-                0.7,
-                random_range::<f32>(0.0, 1.0),
-            );
+            let color = color::hsl(default_poly_hue, 0.7, rng.gen_range(0.0..1.0));
             polygons.push(Poly { polygon, color });
         }
     }
     Model {
+        window,
+        recording: None,
         polygons,
         random_points: vertices,
         center_points: sample_points,
     }
 }
 
-fn update(_app: &App, _model: &mut Model, _update: Update) {}
+fn step(_model: &mut Model, _update: Update) {}
 
-fn view(app: &App, model: &Model, frame: Frame) {
-    let draw = app.draw();
-    draw.background().color(WHITE);
+fn update(_app: &App, model: &mut Model, update: Update) {
+    step(model, update);
+}
+
+fn scene(model: &Model) -> Scene {
+    let mut scene = Scene::new(model.window, WHITE);
 
     model.polygons.iter().for_each(|poly| {
         // only vary the luminescense.
-        draw.polygon()
-            .points(poly.polygon.clone())
-            .color(poly.color);
+        let color: Srgb = poly.color.into();
+        scene.polygon(poly.polygon.clone(), color.into_format::<u8>());
     });
 
     // model.center_points.iter().for_each(|&p| {
-    //     draw.ellipse().xy(p).radius(2.0).color(RED);
+    //     scene.ellipse(p, 2.0, RED);
     // });
 
     // model.random_points.iter().for_each(|&p| {
-    //     draw.ellipse().xy(p).radius(2.0).color(BLUE);
+    //     scene.ellipse(p, 2.0, BLUE);
     // });
 
+    scene
+}
+
+fn view(app: &App, model: &Model, frame: Frame) {
+    let draw = app.draw();
+    let scene = scene(model);
+    scene.draw(&draw);
     draw.to_frame(app, &frame).unwrap();
 
-    if RECORD && app.elapsed_frames() < MAX_RECORDABLE_FRAMES {
-        // Capture the frame!
-        let file_path = captured_frame_path(app, &frame);
-        app.main_window().capture_frame(file_path);
+    if frame.nth() == 0 {
+        canvas::export::write_requested(&scene, &metadata(model), &Args::from_env());
+    }
+
+    if let Some(recording) = &model.recording {
+        recording
+            .add_scene(recording.fast_forward() + frame.nth(), scene)
+            .expect("failed to record frame");
+    }
+}
+
+// Wait for the recorded frames to be written.
+fn exit(_app: &App, model: Model) {
+    if let Some(recording) = &model.recording {
+        recording.finish().expect("failed to finish the recording");
     }
 }
 
-fn captured_frame_path(app: &App, frame: &Frame) -> std::path::PathBuf {
-    // Create a path that we want to save this frame to.
-    app.project_path()
-        .expect("failed to locate `project_path`")
-        // Capture all frames to a directory called `<path_to_project>/frames`.
-        .join("frames")
-        // Name each file after the number of the frame. Numbers must have 5 digts, padded with 0 at start.
-        .join(format!("{:05}", frame.nth()))
-        // The extension will be PNG. We also support tiff, bmp, gif, jpeg, webp and some others.
-        .with_extension("png")
+/// What went into the model, stamped into every PNG so that it can be rendered again.
+fn metadata(model: &Model) -> Metadata {
+    Metadata::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+        .with("Polygons", model.polygons.len())
+        .with("Window", format!("{}x{}", model.window.w(), model.window.h()))
 }
@@ -1,10 +1,17 @@
+#![allow(clippy::needless_return, clippy::let_and_return)]
+
+use canvas::args::Args;
+use canvas::metadata::Metadata;
+use canvas::offline::Offline;
+use canvas::record::{FrameRange, Recording};
+use canvas::Scene;
 use nannou::color::rgb_u32;
-use nannou::noise::{NoiseFn, Perlin, Seedable};
+use nannou::noise::{Fbm, MultiFractal, NoiseFn, Seedable};
 use nannou::prelude::*;
-use nannou::rand::{prelude::SliceRandom, thread_rng};
+use nannou::rand::{prelude::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
-
-const MAX_RECORDABLE_FRAMES: u64 = 1;
+use std::path::PathBuf;
 
 // const WEIGHTED_COLORS: [((u8, u8, u8), f32); 5] = [
 //     ((0x4C, 0xBF, 0xC7), 0.2),
@@ -39,51 +46,147 @@ const WEIGHTED_COLORS: [((u8, u8, u8), f32); 4] = [
 // ];
 
 const BACKGROUND: u32 = 0xE8B761;
+// Distance in pixels under which line end points are treated as the same point.
+const LINE_TOLERANCE: f32 = 1e-3;
 
 fn main() {
     env_logger::init();
-    nannou::app(model).update(update).simple_window(view).run();
+    let args = Args::from_env();
+    canvas::seed::master();
+    if args.flag("headless") {
+        render_headless(&args);
+        return;
+    }
+    if let Some(offline) = Offline::from_args(&args) {
+        render_offline(&args, &offline);
+        return;
+    }
+    nannou::app(model)
+        .update(update)
+        .simple_window(view)
+        .exit(exit)
+        .run();
+}
+
+// Render a single frame on the CPU and write it to a PNG, without creating a window.
+fn render_headless(args: &Args) {
+    let (model, (w, h)) = headless_model(args);
+    let path = args
+        .value("out")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("frames").join("tenthousand.png"));
+    let (scene, (w, h)) = canvas::print::requested(scene(&model), args, (w, h));
+    // Large prints are rendered in tiles, see `canvas::tiles`.
+    let metadata = metadata(&model);
+    canvas::tiles::save_requested(&scene, (w, h), &path, &metadata, args)
+        .expect("failed to save image");
+    println!("Saved {}", path.display());
+    canvas::export::write_requested(&scene, &metadata, args);
+}
+
+// Step through `--offline` frames of simulated time and record every one, without a window.
+fn render_offline(args: &Args, offline: &Offline) {
+    let (mut model, size) = headless_model(args);
+    let recording = Recording::from_args(
+        args,
+        PathBuf::from("frames"),
+        Some(FrameRange::first(offline.frames)),
+        metadata(&model),
+        animation_palette(),
+    )
+    .expect("offline renders are always recorded");
+    offline
+        .run(&mut model, &recording, size, step, scene)
+        .expect("failed to record frame");
+}
+
+// The model to render without a window and the size to render it at. A loaded model renders at
+// the size it was generated at unless told otherwise.
+fn headless_model(args: &Args) -> (Model, (u32, u32)) {
+    let size = args.size("size");
+    let model = canvas::archive::load_or_generate(args, || {
+        let (w, h) = size.unwrap_or((1024, 768));
+        let mut rng = canvas::seed::rng(canvas::seed::master());
+        generate(Rect::from_w_h(w as f32, h as f32), &mut rng)
+    });
+    let size = size.unwrap_or((model.window.w() as u32, model.window.h() as u32));
+    (model, size)
 }
 
 type Triangle = geom::Tri<[f32; 2]>;
 
+#[derive(Serialize, Deserialize)]
 struct Mesh {
+    #[serde(with = "canvas::archive::tris")]
     triangles: Vec<Triangle>,
     color: Srgba<u8>,
 }
 
+#[derive(Serialize, Deserialize)]
 struct Model {
+    #[serde(with = "canvas::archive::rect")]
+    window: Rect,
+    // Where frames are recorded to, never archived.
+    #[serde(skip)]
+    recording: Option<Recording>,
     // Art fields BELOW
     meshes: Vec<Mesh>,
 }
 
 fn model(app: &App) -> Model {
-    let window = app.window_rect();
+    let args = Args::from_env();
+    let mut model = canvas::archive::load_or_generate(&args, || {
+        let mut rng = canvas::seed::rng(canvas::seed::master());
+        generate(app.window_rect(), &mut rng)
+    });
+    // Only the first frame is saved unless the command line asks for more, see `canvas::record`.
+    model.recording = Recording::from_args(
+        &args,
+        app.project_path()
+            .expect("failed to locate `project_path`")
+            .join("frames"),
+        Some(FrameRange::first(1)),
+        metadata(&model),
+        animation_palette(),
+    );
+    model
+}
+
+// The colours a recorded GIF is quantized to.
+fn animation_palette() -> Vec<[u8; 3]> {
+    let background = (
+        (BACKGROUND >> 16) as u8,
+        (BACKGROUND >> 8) as u8,
+        BACKGROUND as u8,
+    );
+    canvas::animation::palette(&WEIGHTED_COLORS, background)
+}
 
+fn generate<R: Rng>(window: Rect, rng: &mut R) -> Model {
     let mut meshes = Vec::new();
 
     // use a reusable perlin noise map which update() will move the triangles over.
-    for i in 0..100 {
+    for _ in 0..100 {
         let xy = Vec2::new(
-            random_range::<f32>(window.left(), window.right()),
-            random_range::<f32>(window.bottom(), window.top()),
+            rng.gen_range(window.left()..window.right()),
+            rng.gen_range(window.bottom()..window.top()),
         );
         let wh = Vec2::new(
-            random_range::<f32>(window.w() / 4f32, window.w()),
-            random_range::<f32>(window.h() / 4f32, window.h()),
+            rng.gen_range(window.w() / 4f32..window.w()),
+            rng.gen_range(window.h() / 4f32..window.h()),
         );
         let rect = Rect::from_xy_wh(xy, wh);
-        let mut rng = thread_rng();
 
 
         // create subdivisions from window rect.
-        let mut triangles = subtriangles(&rect, random_range(4, 6));
-        // triangles.shuffle(&mut rng);
+        let mut triangles = subtriangles(&rect, rng.gen_range(4..6));
+        // triangles.shuffle(rng);
         triangles = triangles.into_iter().take(10_000 / 100).collect();
 
         // Shift the triangles using the perlin noise for multiple iterations.
-        let perlin = Perlin::new().set_seed(i as u32);
-        let num_iterations = random_range::<i32>(100, 250);
+        // Drawn from the seeded rng, so every `--seed` shifts the meshes over a different field.
+        let perlin = perlin(rng.gen());
+        let num_iterations = rng.gen_range(100..250);
         for _ in 0..num_iterations {
             triangles = triangles
                 .iter()
@@ -93,20 +196,24 @@ fn model(app: &App) -> Model {
 
         // Pick an rgba color for the mesh
         let color_bytes = WEIGHTED_COLORS
-            .choose_weighted(&mut rng, |item| item.1)
+            .choose_weighted(rng, |item| item.1)
             .unwrap()
             .0;
         let color = nannou::color::srgba(
             color_bytes.0,
             color_bytes.1,
             color_bytes.2,
-            random_range::<u8>(1, 255),
+            rng.gen_range(1..255),
         );
         let mesh = Mesh { triangles, color };
         meshes.push(mesh);
     }
 
-    Model { meshes }
+    Model {
+        window,
+        recording: None,
+        meshes,
+    }
 }
 
 fn subtriangles(rect: &Rect, levels: i32) -> Vec<Triangle> {
@@ -130,9 +237,19 @@ fn convert_ratio_to_heading(ratio: f64) -> Vec2 {
     heading_vector
 }
 
-fn update(_app: &App, _model: &mut Model, _update: Update) {}
+fn step(_model: &mut Model, _update: Update) {}
 
-fn noise_shifted_triangle(tri: &geom::Tri<[f32; 2]>, noise: &Perlin) -> geom::Tri<[f32; 2]> {
+fn update(_app: &App, model: &mut Model, update: Update) {
+    step(model, update);
+}
+
+// noise 0.7 exports two structs named `Perlin` and neither has a path of its own, so the field is
+// a single octave of `Fbm`, which returns its one Perlin source's value unchanged.
+fn perlin(seed: u32) -> Fbm {
+    Fbm::new().set_octaves(1).set_seed(seed)
+}
+
+fn noise_shifted_triangle(tri: &geom::Tri<[f32; 2]>, noise: &Fbm) -> geom::Tri<[f32; 2]> {
     let f32_verts: [[f32; 2]; 3] = tri.0;
     let verts: [[f64; 2]; 3] = [
         [f32_verts[0][0] as f64, f32_verts[0][1] as f64],
@@ -154,54 +271,71 @@ fn noise_shifted_triangle(tri: &geom::Tri<[f32; 2]>, noise: &Perlin) -> geom::Tr
         verts[2][1] * 0.001f64,
         0.04213f64,
     ]));
-    return geom::Tri::from_index_tri(
+    geom::Tri::from_index_tri(
         &[
             [
-                f32_verts[0][0] + heading_a.x as f32,
-                f32_verts[0][1] + heading_a.y as f32,
+                f32_verts[0][0] + heading_a.x,
+                f32_verts[0][1] + heading_a.y,
             ],
             [
-                f32_verts[1][0] + heading_b.x as f32,
-                f32_verts[1][1] + heading_b.y as f32,
+                f32_verts[1][0] + heading_b.x,
+                f32_verts[1][1] + heading_b.y,
             ],
             [
-                f32_verts[2][0] + heading_c.x as f32,
-                f32_verts[2][1] + heading_c.y as f32,
+                f32_verts[2][0] + heading_c.x,
+                f32_verts[2][1] + heading_c.y,
             ],
         ],
         &[0, 1, 2],
-    );
+    )
 }
 
-fn view(app: &App, model: &Model, frame: Frame) {
-    let draw = app.draw();
-    draw.background().color(rgb_u32(BACKGROUND));
+fn scene(model: &Model) -> Scene {
+    let mut scene = Scene::new(model.window, rgb_u32(BACKGROUND));
     for mesh in &model.meshes {
-        for triangles in &mesh.triangles {
-            draw.polyline()
-                .color(mesh.color)
-                .weight(1.5f32)
-                .points(triangles.to_vec());
+        // Neighbouring triangles share edges, so stroke the merged line set rather than each
+        // triangle to avoid darkening shared edges with the translucent colors.
+        let triangles: Vec<Vec<Vec2>> = mesh
+            .triangles
+            .iter()
+            .map(|tri| tri.vertices().map(Vec2::from).collect())
+            .collect();
+        for line in canvas::lines::simplify(&triangles, LINE_TOLERANCE) {
+            scene.polyline(line, mesh.color, 1.5f32);
         }
     }
+    scene
+}
 
+fn view(app: &App, model: &Model, frame: Frame) {
+    let draw = app.draw();
+    let scene = scene(model);
+    scene.draw(&draw);
     draw.to_frame(app, &frame).unwrap();
 
-    if app.elapsed_frames() < MAX_RECORDABLE_FRAMES {
-        // Capture the frame!
-        let file_path = captured_frame_path(app, &frame);
-        app.main_window().capture_frame(file_path);
+    if frame.nth() == 0 {
+        canvas::export::write_requested(&scene, &metadata(model), &Args::from_env());
+    }
+
+    if let Some(recording) = &model.recording {
+        recording
+            .add_scene(recording.fast_forward() + frame.nth(), scene)
+            .expect("failed to record frame");
+    }
+}
+
+// Wait for the recorded frames to be written.
+fn exit(_app: &App, model: Model) {
+    if let Some(recording) = &model.recording {
+        recording.finish().expect("failed to finish the recording");
     }
 }
 
-fn captured_frame_path(app: &App, frame: &Frame) -> std::path::PathBuf {
-    // Create a path that we want to save this frame to.
-    app.project_path()
-        .expect("failed to locate `project_path`")
-        // Capture all frames to a directory called `<path_to_project>/frames`.
-        .join("frames")
-        // Name each file after the number of the frame. Numbers must have 5 digts, padded with 0 at start.
-        .join(format!("{:05}", frame.nth()))
-        // The extension will be PNG. We also support tiff, bmp, gif, jpeg, webp and some others.
-        .with_extension("png")
+/// What went into the model, stamped into every PNG so that it can be rendered again.
+fn metadata(model: &Model) -> Metadata {
+    Metadata::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+        .with("Palette", canvas::metadata::palette(&WEIGHTED_COLORS))
+        .with("Background", format!("#{:06x}", BACKGROUND))
+        .with("Meshes", model.meshes.len())
+        .with("Window", format!("{}x{}", model.window.w(), model.window.h()))
 }